@@ -0,0 +1,143 @@
+//! Machine-readable dumps of the parse tree. For JSON, serialize the tree
+//! with the `serde` feature, e.g. by `serde_json::to_value(&expr)`.
+
+use crate::{
+    expr::{Expr, UnaryOp},
+    stmt::Stmt,
+};
+use std::fmt::Write;
+
+impl Expr {
+    /// Renders the tree as an S-expression, e.g. `(+ 1 (* 2 x))`.
+    ///
+    /// Explicit parentheses are implied by the nesting and not shown; calls
+    /// are written as `(call f args...)`.
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        write_sexpr(&mut out, self);
+        out
+    }
+    /// Renders the tree in postfix order, e.g. `1 2 x * +`, in the syntax
+    /// accepted by [`parse_rpn`](crate::parse::parse_rpn). Negation is written
+    /// `0 x -` and a call as `args... f(n)`.
+    pub fn to_rpn(&self) -> String {
+        let mut tokens = Vec::new();
+        push_rpn(&mut tokens, self);
+        tokens.join(" ")
+    }
+}
+
+impl Stmt {
+    /// Renders the statement as an S-expression like [`Expr::to_sexpr`], a
+    /// definition as `(define (f x) body)`.
+    pub fn to_sexpr(&self) -> String {
+        match self {
+            Stmt::Expr(expr) => expr.to_sexpr(),
+            Stmt::Define {
+                ident,
+                params,
+                body,
+                ..
+            } => {
+                let mut out = format!("(define ({}", ident);
+                for param in params {
+                    out.push(' ');
+                    out.push_str(param);
+                }
+                out.push_str(") ");
+                write_sexpr(&mut out, body);
+                out.push(')');
+                out
+            }
+        }
+    }
+}
+
+/// A pending step of [`write_sexpr`], which works from a stack like the other
+/// walks of [`Expr`]. Pieces are pushed in reverse, as the last one is written
+/// first.
+enum Piece<'a> {
+    Text(&'a str),
+    Expr(&'a Expr),
+}
+
+fn write_sexpr(out: &mut String, expr: &Expr) {
+    let mut stack = vec![Piece::Expr(expr)];
+    while let Some(piece) = stack.pop() {
+        let expr = match piece {
+            Piece::Text(text) => {
+                out.push_str(text);
+                continue;
+            }
+            Piece::Expr(expr) => expr,
+        };
+        match expr {
+            Expr::Int(n) => {
+                let _ = write!(out, "{}", n);
+            }
+            #[cfg(feature = "float")]
+            Expr::Float(lit) => out.push_str(lit),
+            Expr::Binary(lhs, op, rhs) => {
+                let _ = write!(out, "({} ", op.symbol());
+                stack.extend([
+                    Piece::Text(")"),
+                    Piece::Expr(rhs),
+                    Piece::Text(" "),
+                    Piece::Expr(lhs),
+                ]);
+            }
+            Expr::Unary(op, expr) => {
+                let _ = write!(out, "({} ", op.symbol());
+                stack.extend([Piece::Text(")"), Piece::Expr(expr)]);
+            }
+            Expr::Paren(expr) => stack.push(Piece::Expr(expr)),
+            Expr::Variable(ident) => out.push_str(ident),
+            Expr::Call(ident, args) => {
+                let _ = write!(out, "(call {}", ident);
+                stack.push(Piece::Text(")"));
+                for arg in args.iter().rev() {
+                    stack.extend([Piece::Expr(arg), Piece::Text(" ")]);
+                }
+            }
+        }
+    }
+}
+
+fn push_rpn(tokens: &mut Vec<String>, root: &Expr) {
+    // Nodes with whether their operands have been written.
+    let mut stack = vec![(root, false)];
+    while let Some((expr, expanded)) = stack.pop() {
+        if expanded {
+            match expr {
+                Expr::Binary(_, op, _) => tokens.push(op.symbol().to_string()),
+                Expr::Unary(UnaryOp::Minus, _) => tokens.push("-".to_string()),
+                Expr::Call(ident, args) => tokens.push(format!("{}({})", ident, args.len())),
+                _ => unreachable!(),
+            }
+            continue;
+        }
+        match expr {
+            Expr::Int(n) if n.sign() == num::bigint::Sign::Minus => {
+                tokens.push("0".to_string());
+                tokens.push(n.magnitude().to_string());
+                tokens.push("-".to_string());
+            }
+            Expr::Int(n) => tokens.push(n.to_string()),
+            #[cfg(feature = "float")]
+            Expr::Float(lit) => tokens.push(lit.clone()),
+            Expr::Binary(lhs, _, rhs) => stack.extend([(expr, true), (rhs, false), (lhs, false)]),
+            Expr::Unary(UnaryOp::Plus, operand) | Expr::Paren(operand) => {
+                stack.push((operand, false))
+            }
+            Expr::Unary(UnaryOp::Minus, operand) => {
+                tokens.push("0".to_string());
+                stack.extend([(expr, true), (operand, false)]);
+            }
+            Expr::Variable(ident) => tokens.push(ident.clone()),
+            Expr::Call(_, args) => {
+                stack.push((expr, true));
+                stack.extend(args.iter().rev().map(|arg| (arg, false)));
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{
+    eval::{Environment, EvalResult, Function, NativeFn},
+    expr::BinaryOp,
+    math,
+    value::Value,
+};
+use num::BigInt;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub params: &'static [&'static str],
+    pub doc: &'static str,
+    /// The result only depends on the arguments, so user functions calling it
+    /// may be memoized.
+    pub pure: bool,
+    pub func: NativeFn,
+}
+
+/// Functions available in every [`Environment`](crate::eval::Environment).
+///
+/// Adding a builtin only requires a new entry here.
+pub static BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "pow",
+        params: &["x", "y"],
+        doc: "x raised to the power y, same as `x ** y`",
+        pure: true,
+        func: pow,
+    },
+    Builtin {
+        name: "is_square",
+        params: &["n"],
+        doc: "1 if n is the square of an integer, otherwise 0",
+        pure: true,
+        func: is_square,
+    },
+    Builtin {
+        name: "perfect_power",
+        params: &["n"],
+        doc: "the largest exp with n == base ** exp for an integer base, or 1 if none",
+        pure: true,
+        func: perfect_power,
+    },
+    Builtin {
+        name: "vars",
+        params: &[],
+        doc: "lists the variables and their values",
+        pure: false,
+        func: vars,
+    },
+    Builtin {
+        name: "fns",
+        params: &[],
+        doc: "lists the user-defined and registered functions",
+        pure: false,
+        func: fns,
+    },
+    #[cfg(feature = "float")]
+    Builtin {
+        name: "precision",
+        params: &["bits"],
+        doc: "sets the mantissa bits of subsequent float arithmetic",
+        pure: false,
+        func: precision,
+    },
+];
+
+impl Builtin {
+    pub fn get(ident: &str) -> Option<&'static Builtin> {
+        BUILTINS.iter().find(|builtin| builtin.name == ident)
+    }
+    pub fn to_function(&'static self) -> Function {
+        Function::Native(self)
+    }
+}
+
+fn pow(env: &mut Environment, args: &[Value]) -> EvalResult<Value> {
+    env.binary(BinaryOp::Pow, args[0].clone(), args[1].clone())
+}
+
+fn is_square(_env: &mut Environment, args: &[Value]) -> EvalResult<Value> {
+    Ok(BigInt::from(math::is_square(args[0].as_int()?) as u8).into())
+}
+
+fn perfect_power(env: &mut Environment, args: &[Value]) -> EvalResult<Value> {
+    let check = || env.check_interrupt();
+    let exp = match math::perfect_power(args[0].as_int()?, &check)? {
+        Some((_, exp)) => exp,
+        None => 1,
+    };
+    Ok(BigInt::from(exp).into())
+}
+
+fn vars(env: &mut Environment, _args: &[Value]) -> EvalResult<Value> {
+    let mut lines: Vec<_> = env
+        .variables()
+        .map(|(ident, value)| format!("{} = {}", ident, value))
+        .collect();
+    lines.sort();
+    Ok(Value::Str(lines.join("\n")))
+}
+
+fn fns(env: &mut Environment, _args: &[Value]) -> EvalResult<Value> {
+    let mut lines: Vec<_> = env
+        .functions()
+        .map(|(ident, function)| match function {
+            Function::User { params, body, .. } => {
+                format!("{}({}) = {}", ident, params.join(", "), body)
+            }
+            _ => format!("{}(...)", ident),
+        })
+        .collect();
+    lines.sort();
+    Ok(Value::Str(lines.join("\n")))
+}
+
+#[cfg(feature = "float")]
+fn precision(env: &mut Environment, args: &[Value]) -> EvalResult<Value> {
+    use crate::eval::EvalErrorKind;
+    use num::ToPrimitive;
+
+    match args[0].as_int()?.to_usize() {
+        Some(bits @ 1..=0x100_0000) => {
+            env.set_precision(bits);
+            Ok(args[0].clone())
+        }
+        _ => Err(EvalErrorKind::InvalidArgument.into()),
+    }
+}
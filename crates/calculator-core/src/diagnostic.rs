@@ -0,0 +1,87 @@
+//! Rendering of errors against the input they refer to.
+
+use crate::token::Span;
+use std::fmt;
+
+/// An error message and the part of the input it refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl fmt::Display, span: Option<Span>) -> Self {
+        Self {
+            message: message.to_string(),
+            span,
+        }
+    }
+    /// The message, followed by the line of `input` holding the span and
+    /// the span underlined. Lines are numbered if `input` has more than one.
+    pub fn render(&self, input: &str) -> String {
+        let span = match self.span {
+            Some(span) => span,
+            None => return self.message.clone(),
+        };
+        let start = span.start.min(input.len());
+        let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+        let line = &input[line_start..line_end];
+        let span = Span::new(
+            start - line_start,
+            span.end.clamp(start, line_end) - line_start,
+        );
+        let location = if input.trim_end().contains('\n') {
+            let number = input[..line_start].matches('\n').count() + 1;
+            format!("line {}: ", number)
+        } else {
+            String::new()
+        };
+        format!(
+            "{}{}\n  {}\n  {}",
+            location,
+            self.message,
+            line,
+            underline(line, span)
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.message.fmt(f)
+    }
+}
+
+/// Marks `span` of `input` with a caret and tildes, to be printed below it:
+///
+/// ```text
+/// 2 + * 3
+///     ^
+/// ```
+///
+/// An empty span, such as the end of the input, gets a single caret.
+pub fn underline(input: &str, span: Span) -> String {
+    let start = span.start.min(input.len());
+    let end = span.end.clamp(start, input.len());
+    let pad = input[..start].chars().map(width).sum();
+    let len = input[start..end].chars().map(width).sum::<usize>().max(1);
+    format!("{}^{}", " ".repeat(pad), "~".repeat(len - 1))
+}
+
+/// Columns taken by `c` in a terminal: two for East Asian wide and
+/// full-width characters, such as `１` or `あ`, otherwise one.
+fn width(c: char) -> usize {
+    match c {
+        '\u{1100}'..='\u{115f}'
+        | '\u{2e80}'..='\u{a4cf}'
+        | '\u{ac00}'..='\u{d7a3}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{fe30}'..='\u{fe4f}'
+        | '\u{ff00}'..='\u{ff60}'
+        | '\u{ffe0}'..='\u{ffe6}'
+        | '\u{20000}'..='\u{3fffd}' => 2,
+        _ => 1,
+    }
+}
@@ -1,15 +1,110 @@
-use crate::expr::Expr;
-use num::BigInt;
-use std::{collections::HashMap, fmt::Display};
+use crate::{
+    builtins::Builtin,
+    expr::{BinaryOp, Expr},
+    memo::{self, Key, Memo},
+    token::Span,
+    value::Value,
+};
+use num::{bigint::Sign, ToPrimitive};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use thiserror::Error;
+use web_time::Instant;
 
 pub trait Eval {
-    type Output: Display;
+    type Output;
     fn eval(self, env: &mut Environment) -> EvalResult<Self::Output>;
+    /// Evaluates under `options`, failing with [`EvalErrorKind::Cancelled`] or
+    /// [`EvalErrorKind::TimedOut`] once they say to stop.
+    fn eval_with(self, env: &mut Environment, options: EvalOptions) -> EvalResult<Self::Output>
+    where
+        Self: Sized,
+    {
+        let outer = mem::replace(&mut env.options, options);
+        let result = self.eval(env);
+        env.options = outer;
+        result
+    }
+}
+
+/// When to give up on an evaluation, see [`Eval::eval_with`].
+#[derive(Debug, Clone, Default)]
+pub struct EvalOptions {
+    pub deadline: Option<Instant>,
+    pub cancel_token: Option<CancelToken>,
+}
+
+impl EvalOptions {
+    /// Sets the deadline to `timeout` from now.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+    pub fn with_cancel_token(mut self, cancel_token: CancelToken) -> Self {
+        self.cancel_token = Some(cancel_token);
+        self
+    }
+}
+
+/// A flag shared between an evaluation and the code that may cancel it, e.g.
+/// a Ctrl-C handler.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+    /// Clears the flag so the token can be used for the next evaluation.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
 }
 
+/// An evaluation error and the expression node it occurred at.
+///
+/// Expressions don't store their position in the input, so the node is given
+/// by its index in post-order, in which
+/// [`parse_with_spans`](crate::parse::parse_with_spans) returns the spans.
 #[derive(Debug, Clone, Error)]
-pub enum EvalError {
+#[error("{kind}")]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    pub node: Option<usize>,
+}
+
+impl EvalError {
+    /// The span of the failed node among the `spans` of the evaluated input.
+    pub fn span(&self, spans: &[Span]) -> Option<Span> {
+        spans.get(self.node?).copied()
+    }
+    pub(crate) fn at_node(mut self, node: Option<usize>) -> Self {
+        self.node = node;
+        self
+    }
+}
+
+impl From<EvalErrorKind> for EvalError {
+    fn from(kind: EvalErrorKind) -> Self {
+        Self { kind, node: None }
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum EvalErrorKind {
     #[error("devide by zero")]
     DevideByZero,
     #[error("negative power")]
@@ -24,30 +119,495 @@ pub enum EvalError {
     UndefinedFunction,
     #[error("unable to assign")]
     UnableToAssign,
+    #[error("expected integer")]
+    ExpectedInteger,
+    #[error("invalid argument")]
+    InvalidArgument,
+    #[error("cannot assign to constant")]
+    CannotAssignConstant,
+    #[error("type mismatch")]
+    TypeMismatch,
+    #[error("evaluation cancelled")]
+    Cancelled,
+    #[error("evaluation timed out")]
+    TimedOut,
+    #[error("limit exceeded")]
+    LimitExceeded,
 }
 
 pub type EvalResult<T> = Result<T, EvalError>;
 
-#[derive(Debug, Default)]
+/// Default precision of floats in bits.
+pub const DEFAULT_PRECISION: usize = 128;
+
+/// Default of [`Limits::max_call_depth`].
+pub const DEFAULT_CALL_DEPTH: usize = 256;
+
+/// Caps that turn runaway computations into [`EvalErrorKind::LimitExceeded`]
+/// instead of exhausting memory or the call stack. `None` means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Largest exponent accepted by `**` and `pow` on integers.
+    pub max_exponent: Option<u64>,
+    /// Largest integer result in bits.
+    pub max_bits: Option<u64>,
+    /// Deepest nesting of calls of user-defined functions, each of which
+    /// takes a few kilobytes of the call stack.
+    pub max_call_depth: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_exponent: None,
+            // 8 MiB per integer.
+            max_bits: Some(1 << 26),
+            max_call_depth: Some(DEFAULT_CALL_DEPTH),
+        }
+    }
+}
+
+/// Variables bound by one scope.
+pub type Scope = HashMap<String, Value>;
+
+#[derive(Debug)]
 pub struct Environment {
-    variables: HashMap<String, BigInt>,
+    /// Innermost scope last. The first scope holds the globals and is never
+    /// popped.
+    scopes: Vec<Scope>,
+    /// Global variables that cannot be reassigned.
+    constants: HashSet<String>,
     functions: HashMap<String, Function>,
+    precision: usize,
+    limits: Limits,
+    /// Options of the running [`Eval::eval_with`].
+    options: EvalOptions,
+    /// `Some` if memoization is enabled.
+    memo: Option<Memo>,
+    /// Calls of user-defined functions being evaluated.
+    depth: usize,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            scopes: vec![Scope::new()],
+            constants: HashSet::new(),
+            functions: HashMap::new(),
+            precision: DEFAULT_PRECISION,
+            limits: Limits::default(),
+            options: EvalOptions::default(),
+            memo: None,
+            depth: 0,
+        }
+    }
 }
 
 impl Environment {
-    pub fn get_variable(&self, ident: &str) -> EvalResult<&BigInt> {
-        self.variables
-            .get(ident)
-            .ok_or_else(|| EvalError::UndefinedVariable)
+    /// Looks up a variable from the innermost scope outwards.
+    pub fn get_variable(&self, ident: &str) -> EvalResult<&Value> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(ident))
+            .ok_or(EvalErrorKind::UndefinedVariable.into())
+    }
+    /// Binds a variable in the innermost scope.
+    pub fn set_variable(&mut self, ident: String, expr: Value) {
+        self.scopes.last_mut().unwrap().insert(ident, expr);
     }
-    pub fn set_variable(&mut self, ident: String, expr: BigInt) {
-        *self.variables.entry(ident).or_default() = expr;
+    /// Variables visible from the innermost scope, in no particular order.
+    pub fn variables(&self) -> impl Iterator<Item = (&str, &Value)> {
+        let mut seen = HashSet::new();
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter())
+            .filter(move |(ident, _)| seen.insert(ident.as_str()))
+            .map(|(ident, value)| (ident.as_str(), value))
     }
-    pub fn call(&self, ident: &str, _args: Vec<Expr>) -> EvalResult<&Function> {
+    /// User-defined and registered functions, in no particular order. See
+    /// [`BUILTINS`](crate::builtins::BUILTINS) for the builtins.
+    pub fn functions(&self) -> impl Iterator<Item = (&str, &Function)> {
         self.functions
-            .get(ident)
-            .ok_or_else(|| EvalError::UndefinedFunction)
+            .iter()
+            .map(|(ident, function)| (ident.as_str(), function))
+    }
+    /// Binds a global variable that assignments cannot change.
+    pub fn set_constant(&mut self, ident: String, value: Value) {
+        self.clear_memo();
+        self.scopes[0].insert(ident.clone(), value);
+        self.constants.insert(ident);
+    }
+    pub fn is_constant(&self, ident: &str) -> bool {
+        self.constants.contains(ident)
+    }
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope::new());
+    }
+    /// Drops the innermost scope and its bindings.
+    ///
+    /// # Panics
+    ///
+    /// Panics if only the global scope is left.
+    pub fn pop_scope(&mut self) -> Scope {
+        assert!(self.scopes.len() > 1, "cannot pop the global scope");
+        self.scopes.pop().unwrap()
+    }
+    /// Saves the current variables, functions and settings.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            scopes: self.scopes.clone(),
+            constants: self.constants.clone(),
+            functions: self.functions.clone(),
+            precision: self.precision,
+            limits: self.limits,
+        }
+    }
+    /// Reverts to a state saved with [`Environment::snapshot`].
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.scopes = snapshot.scopes;
+        self.constants = snapshot.constants;
+        self.functions = snapshot.functions;
+        self.precision = snapshot.precision;
+        self.limits = snapshot.limits;
+        self.clear_memo();
+    }
+    /// Runs `f`, undoing all of its changes to the environment if it fails.
+    pub fn transaction<T>(&mut self, f: impl FnOnce(&mut Self) -> EvalResult<T>) -> EvalResult<T> {
+        let snapshot = self.snapshot();
+        let result = f(self);
+        if result.is_err() {
+            self.restore(snapshot);
+        }
+        result
     }
+    /// Mantissa bits used for float arithmetic.
+    pub fn precision(&self) -> usize {
+        self.precision
+    }
+    pub fn set_precision(&mut self, precision: usize) {
+        self.clear_memo();
+        self.precision = precision;
+    }
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.clear_memo();
+        self.limits = limits;
+    }
+    /// Caches the results of calls to pure functions, so repeated calls such
+    /// as `f(x)` for the same `x` are only evaluated once. A user-defined
+    /// function is pure if its body has no assignments, reads no variables
+    /// other than its parameters and constants, and only calls pure functions.
+    pub fn set_memoize(&mut self, enabled: bool) {
+        self.memo = enabled.then(Memo::default);
+    }
+    pub fn is_memoized(&self) -> bool {
+        self.memo.is_some()
+    }
+    fn clear_memo(&mut self) {
+        if let Some(memo) = &mut self.memo {
+            *memo = Memo::default();
+        }
+    }
+    fn is_pure(&mut self, ident: &str) -> bool {
+        match self.memo.as_ref().and_then(|memo| memo.purity(ident)) {
+            Some(pure) => pure,
+            None => {
+                let pure = memo::is_pure(self, ident, &mut HashSet::new());
+                if let Some(memo) = &mut self.memo {
+                    memo.set_purity(ident, pure);
+                }
+                pure
+            }
+        }
+    }
+    /// Applies `op` under the precision, [`Limits`] and interruption of this
+    /// environment.
+    pub(crate) fn binary(&self, op: BinaryOp, l: Value, r: Value) -> EvalResult<Value> {
+        if let (Value::Int(l), Value::Int(r)) = (&l, &r) {
+            // Lower bounds of the result size, checked before computing it.
+            let bits = match op {
+                BinaryOp::Mul => (l.bits() + r.bits()).saturating_sub(1),
+                BinaryOp::Pow if l.bits() > 1 && r.sign() == Sign::Plus => {
+                    let exponent = r.to_u64().ok_or(EvalErrorKind::LimitExceeded)?;
+                    if self.limits.max_exponent.is_some_and(|max| exponent > max) {
+                        Err(EvalErrorKind::LimitExceeded)?
+                    }
+                    (l.bits() - 1).saturating_mul(exponent)
+                }
+                _ => 0,
+            };
+            if self.limits.max_bits.is_some_and(|max| bits > max) {
+                Err(EvalErrorKind::LimitExceeded)?
+            }
+        }
+        let check = || self.check_interrupt();
+        let value = l.binary_with(op, r, self.precision, &check)?;
+        match &value {
+            Value::Int(n) if self.limits.max_bits.is_some_and(|max| n.bits() > max) => {
+                Err(EvalErrorKind::LimitExceeded.into())
+            }
+            _ => Ok(value),
+        }
+    }
+    /// Fails if the running evaluation has been cancelled or is past its
+    /// deadline. Called between the steps of long computations.
+    pub fn check_interrupt(&self) -> EvalResult<()> {
+        let options = &self.options;
+        if options
+            .cancel_token
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+        {
+            Err(EvalErrorKind::Cancelled.into())
+        } else if options
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            Err(EvalErrorKind::TimedOut.into())
+        } else {
+            Ok(())
+        }
+    }
+    /// Looks up a user-defined function, falling back to the builtins.
+    pub fn get_function(&self, ident: &str) -> EvalResult<Function> {
+        match self.functions.get(ident) {
+            Some(function) => Ok(function.clone()),
+            None => Builtin::get(ident)
+                .map(Builtin::to_function)
+                .ok_or(EvalErrorKind::UndefinedFunction.into()),
+        }
+    }
+    pub fn set_function(&mut self, ident: String, function: Function) {
+        self.clear_memo();
+        self.functions.insert(ident, function);
+    }
+    /// Registers a function implemented by the embedding application.
+    ///
+    /// The function accepts any number of arguments; return
+    /// [`EvalErrorKind::InvalidArgumentLength`] to reject a call.
+    pub fn register_fn<F>(&mut self, ident: impl Into<String>, func: F)
+    where
+        F: Fn(&[Value]) -> EvalResult<Value> + Send + Sync + 'static,
+    {
+        self.set_function(ident.into(), Function::Host(Arc::new(func)));
+    }
+    pub fn call(&mut self, ident: &str, args: &[Expr]) -> EvalResult<Value> {
+        let function = self.get_function(ident)?;
+        if function.arity().is_some_and(|arity| arity != args.len()) {
+            Err(EvalErrorKind::InvalidArgumentLength)?
+        }
+        let args = args
+            .iter()
+            .map(|arg| arg.eval(self))
+            .collect::<EvalResult<Vec<_>>>()?;
+        self.apply(ident, function, args)
+    }
+    /// Calls `function`, named `ident`, with evaluated arguments whose count
+    /// has been checked against its arity.
+    pub(crate) fn apply(
+        &mut self,
+        ident: &str,
+        function: Function,
+        args: Vec<Value>,
+    ) -> EvalResult<Value> {
+        // Builtins are cheap enough to call again.
+        let memoize =
+            self.memo.is_some() && matches!(function, Function::User { .. }) && self.is_pure(ident);
+        let key = memoize.then(|| Key::new(ident, &args)).flatten();
+        if let Some(value) = key.as_ref().and_then(|key| self.memo.as_ref()?.get(key)) {
+            return Ok(value.clone());
+        }
+        let value = match function {
+            Function::Native(builtin) => (builtin.func)(self, &args),
+            Function::Host(func) => func(&args),
+            Function::User { params, body, .. } => {
+                if self
+                    .limits
+                    .max_call_depth
+                    .is_some_and(|max| self.depth >= max)
+                {
+                    Err(EvalErrorKind::LimitExceeded)?
+                }
+                // Bodies only see the globals and their own parameters, not
+                // the locals of the caller.
+                let callers = self.scopes.split_off(1);
+                self.push_scope();
+                for (param, arg) in params.into_iter().zip(args) {
+                    self.set_variable(param, arg);
+                }
+                self.depth += 1;
+                let result = body.as_ref().eval(self);
+                self.depth -= 1;
+                self.scopes.truncate(1);
+                self.scopes.extend(callers);
+                result
+            }
+        }?;
+        if let (Some(key), Some(memo)) = (key, &mut self.memo) {
+            memo.insert(key, value.clone());
+        }
+        Ok(value)
+    }
+}
+
+/// A saved state of an [`Environment`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    scopes: Vec<Scope>,
+    constants: HashSet<String>,
+    functions: HashMap<String, Function>,
+    precision: usize,
+    limits: Limits,
+}
+
+pub type NativeFn = fn(&mut Environment, &[Value]) -> EvalResult<Value>;
+
+pub type HostFn = Arc<dyn Fn(&[Value]) -> EvalResult<Value> + Send + Sync>;
+
+#[derive(Clone)]
+pub enum Function {
+    /// One of the [`BUILTINS`](crate::builtins::BUILTINS).
+    Native(&'static Builtin),
+    /// Registered with [`Environment::register_fn`].
+    Host(HostFn),
+    /// Defined by the user as `f(x, y) = body ## doc`.
+    User {
+        params: Vec<String>,
+        /// Shared so calls don't clone the tree.
+        body: Arc<Expr>,
+        doc: Option<String>,
+    },
 }
 
-pub type Function = (); // TODO
+impl Function {
+    /// The number of arguments, or `None` if the function is variadic.
+    pub fn arity(&self) -> Option<usize> {
+        match self {
+            Function::Native(builtin) => Some(builtin.params.len()),
+            Function::Host(_) => None,
+            Function::User { params, .. } => Some(params.len()),
+        }
+    }
+    /// Parameter names, if known.
+    pub fn params(&self) -> Option<Vec<&str>> {
+        match self {
+            Function::Native(builtin) => Some(builtin.params.to_vec()),
+            Function::Host(_) => None,
+            Function::User { params, .. } => Some(params.iter().map(String::as_str).collect()),
+        }
+    }
+    /// The expression of a user-defined function.
+    pub fn body(&self) -> Option<&Expr> {
+        match self {
+            Function::User { body, .. } => Some(body),
+            _ => None,
+        }
+    }
+    pub fn doc(&self) -> Option<&str> {
+        match self {
+            Function::Native(builtin) => Some(builtin.doc),
+            Function::Host(_) => None,
+            Function::User { doc, .. } => doc.as_deref(),
+        }
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Function::Native(builtin) => f.debug_tuple("Native").field(builtin).finish(),
+            Function::Host(_) => f.debug_tuple("Host").finish_non_exhaustive(),
+            Function::User { params, body, doc } => f
+                .debug_struct("User")
+                .field("params", params)
+                .field("body", body)
+                .field("doc", doc)
+                .finish(),
+        }
+    }
+}
+
+/// Global variables, constants, user-defined functions and the precision are
+/// persisted. User function bodies are stored as source text; host functions
+/// registered with [`Environment::register_fn`] are not serialized and must be
+/// registered again.
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::{Environment, Function, DEFAULT_PRECISION};
+    use crate::{expr::Expr, parse::parse_from_str, value::Value};
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::sync::Arc;
+
+    #[derive(Serialize, Deserialize)]
+    struct EnvironmentRepr {
+        variables: BTreeMap<String, Value>,
+        #[serde(default)]
+        constants: BTreeSet<String>,
+        functions: BTreeMap<String, UserFunctionRepr>,
+        #[serde(default = "default_precision")]
+        precision: usize,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct UserFunctionRepr {
+        params: Vec<String>,
+        body: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doc: Option<String>,
+    }
+
+    fn default_precision() -> usize {
+        DEFAULT_PRECISION
+    }
+
+    impl Serialize for Environment {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let functions = self
+                .functions
+                .iter()
+                .filter_map(|(ident, function)| match function {
+                    Function::User { params, body, doc } => Some((
+                        ident.clone(),
+                        UserFunctionRepr {
+                            params: params.clone(),
+                            body: body.to_string(),
+                            doc: doc.clone(),
+                        },
+                    )),
+                    _ => None,
+                })
+                .collect();
+            EnvironmentRepr {
+                variables: self.scopes[0].clone().into_iter().collect(),
+                constants: self.constants.iter().cloned().collect(),
+                functions,
+                precision: self.precision,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Environment {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let repr = EnvironmentRepr::deserialize(deserializer)?;
+            let mut env = Environment {
+                scopes: vec![repr.variables.into_iter().collect()],
+                constants: repr.constants.into_iter().collect(),
+                precision: repr.precision,
+                ..Default::default()
+            };
+            for (ident, UserFunctionRepr { params, body, doc }) in repr.functions {
+                let body = parse_from_str::<Expr>(&body)
+                    .map_err(|err| D::Error::custom(format!("function `{}`: {}", ident, err)))?;
+                let body = Arc::new(body);
+                env.set_function(ident, Function::User { params, body, doc });
+            }
+            Ok(env)
+        }
+    }
+}
@@ -1,77 +1,203 @@
 use crate::{
-    eval::{Environment, Eval, EvalError, EvalResult},
-    parse::{Parse, ParseError, ParseResult},
-    token::{Token, TokenStream},
+    eval::{Environment, Eval, EvalErrorKind, EvalResult, Function, NativeFn},
+    parse::{Parse, ParseError, ParseErrorKind, ParseResult},
+    token::{from_superscript, Token, TokenStream},
+    value::Value,
 };
-use num::{traits::Pow, BigInt, Zero};
+use num::BigInt;
+use std::{fmt, mem, ops};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
-    Int(BigInt),
+    Int(#[cfg_attr(feature = "serde", serde(with = "crate::value::bigint_str"))] BigInt),
+    /// Decimal literal, rounded to the precision in effect when evaluated.
+    #[cfg(feature = "float")]
+    Float(String),
     Binary(Box<Expr>, BinaryOp, Box<Expr>),
     Unary(UnaryOp, Box<Expr>),
+    /// A group in `()`, `[]` or `{}`, displayed with parentheses.
     Paren(Box<Expr>),
     Variable(String),
     Call(String, Vec<Expr>),
 }
 
+/// A placeholder left behind when moving a child out of a node, which
+/// [`Drop`] forbids destructuring.
+impl Default for Expr {
+    fn default() -> Self {
+        Expr::Int(BigInt::default())
+    }
+}
+
+/// Drops the nodes one at a time from a work stack rather than by recursion,
+/// so that deep trees, such as that of a long sum, don't overflow the call
+/// stack. Evaluation, display, folding, [visitors](crate::visit) and the dumps
+/// of [`ast`](crate::ast) and [`mathml`](crate::mathml) walk trees the same
+/// way.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_children(&mut stack);
+        while let Some(mut expr) = stack.pop() {
+            expr.take_children(&mut stack);
+        }
+    }
+}
+
 impl Eval for Expr {
-    type Output = BigInt;
+    type Output = Value;
 
     fn eval(self, env: &mut Environment) -> EvalResult<Self::Output> {
-        Ok(match self {
-            Expr::Int(n) => n,
-            Expr::Binary(lhs, BinaryOp::Assign, rhs) => {
-                let r = rhs.eval(env)?;
-                match *lhs {
-                    Expr::Variable(ident) => {
-                        env.set_variable(ident, r.clone());
-                        r
-                    }
-                    _ => Err(EvalError::UnableToAssign)?,
-                }
+        (&self).eval(env)
+    }
+}
+
+/// A pending step of [`Expr`] evaluation.
+enum Task<'a> {
+    Eval(&'a Expr),
+    /// Combines the values of the operands of a binary or unary node.
+    Apply(&'a Expr),
+    /// Calls the function of a call node with the values of its arguments.
+    Call(&'a Expr, Function),
+}
+
+impl<'a> Task<'a> {
+    fn node(&self) -> &'a Expr {
+        match self {
+            Task::Eval(expr) | Task::Apply(expr) | Task::Call(expr, _) => expr,
+        }
+    }
+}
+
+/// Evaluates without consuming the tree, so it can be evaluated repeatedly.
+///
+/// Works from a stack like dropping. Parsing is recursive in the nesting of
+/// brackets, which the parser caps at [`MAX_NESTING`](crate::parse::MAX_NESTING).
+/// Calls of user-defined functions nest on the call stack, up to
+/// [`Limits::max_call_depth`](crate::eval::Limits).
+///
+/// Errors name the failing node by its [post-order index](EvalErrorKind::node);
+/// errors inside a called function are reported at the call.
+impl Eval for &Expr {
+    type Output = Value;
+
+    fn eval(self, env: &mut Environment) -> EvalResult<Self::Output> {
+        let mut tasks = vec![Task::Eval(self)];
+        let mut values: Vec<Value> = Vec::new();
+        while let Some(task) = tasks.pop() {
+            env.check_interrupt()?;
+            let node = task.node();
+            step(env, task, &mut tasks, &mut values)
+                .map_err(|err| err.at_node(post_order_index(self, node)))?;
+        }
+        Ok(values.pop().unwrap())
+    }
+}
+
+fn step<'a>(
+    env: &mut Environment,
+    task: Task<'a>,
+    tasks: &mut Vec<Task<'a>>,
+    values: &mut Vec<Value>,
+) -> EvalResult<()> {
+    match task {
+        Task::Eval(expr) => match expr {
+            Expr::Int(n) => values.push(Value::Int(n.clone())),
+            #[cfg(feature = "float")]
+            Expr::Float(s) => {
+                values.push(Value::Float(crate::value::float::parse(s, env.precision())))
             }
-            Expr::Binary(lhs, op, rhs) => {
-                let (l, r) = (lhs.eval(env)?, rhs.eval(env)?);
-                match op {
-                    BinaryOp::Add => l + r,
-                    BinaryOp::Sub => l - r,
-                    BinaryOp::Mul => l * r,
-                    BinaryOp::Div | BinaryOp::Rem if r.is_zero() => Err(EvalError::DevideByZero)?,
-                    BinaryOp::Div => l / r,
-                    BinaryOp::Rem => l % r,
-                    BinaryOp::Pow => {
-                        if let Some(r) = r.to_biguint() {
-                            l.pow(r)
-                        } else {
-                            Err(EvalError::NegativePower)?
-                        }
-                    }
-                    BinaryOp::Assign => Err(EvalError::Unimplemented)?,
+            Expr::Binary(_, BinaryOp::Assign, rhs) => {
+                tasks.push(Task::Apply(expr));
+                tasks.push(Task::Eval(rhs));
+            }
+            Expr::Binary(lhs, _, rhs) => {
+                tasks.push(Task::Apply(expr));
+                tasks.push(Task::Eval(rhs));
+                tasks.push(Task::Eval(lhs));
+            }
+            Expr::Unary(UnaryOp::Plus, operand) | Expr::Paren(operand) => {
+                tasks.push(Task::Eval(operand))
+            }
+            Expr::Unary(UnaryOp::Minus, operand) => {
+                tasks.push(Task::Apply(expr));
+                tasks.push(Task::Eval(operand));
+            }
+            Expr::Variable(ident) => values.push(env.get_variable(ident).cloned()?),
+            Expr::Call(ident, args) => {
+                let function = env.get_function(ident)?;
+                if function.arity().is_some_and(|arity| arity != args.len()) {
+                    Err(EvalErrorKind::InvalidArgumentLength)?
                 }
+                tasks.push(Task::Call(expr, function));
+                tasks.extend(args.iter().rev().map(Task::Eval));
             }
-            Expr::Unary(op, expr) => match op {
-                UnaryOp::Plus => expr.eval(env)?,
-                UnaryOp::Minus => -expr.eval(env)?,
-            },
-            Expr::Paren(expr) => expr.eval(env)?,
-            Expr::Variable(ident) => env.get_variable(&ident).cloned()?,
-            Expr::Call(s, args) if s.as_str() == "pow" => {
-                if args.len() == 2 {
-                    let mut it = args.into_iter();
-                    let (l, r) = (it.next().unwrap().eval(env)?, it.next().unwrap().eval(env)?);
-                    if let Some(r) = r.to_biguint() {
-                        l.pow(r)
-                    } else {
-                        Err(EvalError::NegativePower)?
+        },
+        Task::Apply(expr) => match expr {
+            Expr::Binary(lhs, BinaryOp::Assign, _) => {
+                let r = values.last().unwrap().clone();
+                match lhs.as_ref() {
+                    Expr::Variable(ident) if env.is_constant(ident) => {
+                        Err(EvalErrorKind::CannotAssignConstant)?
                     }
-                } else {
-                    Err(EvalError::InvalidArgumentLength)?
+                    Expr::Variable(ident) => env.set_variable(ident.clone(), r),
+                    _ => Err(EvalErrorKind::UnableToAssign)?,
                 }
             }
-            Expr::Call(_, _) => Err(EvalError::Unimplemented)?,
-        })
+            Expr::Binary(_, BinaryOp::Custom(op), _) => {
+                let r = values.pop().unwrap();
+                let l = values.pop().unwrap();
+                values.push((op.eval)(env, &[l, r])?);
+            }
+            Expr::Binary(_, op, _) => {
+                let r = values.pop().unwrap();
+                let l = values.pop().unwrap();
+                values.push(env.binary(*op, l, r)?);
+            }
+            Expr::Unary(UnaryOp::Minus, _) => {
+                let value = values.pop().unwrap().negate()?;
+                values.push(value);
+            }
+            _ => unreachable!(),
+        },
+        Task::Call(expr, function) => match expr {
+            Expr::Call(ident, args) => {
+                let args = values.split_off(values.len() - args.len());
+                values.push(env.apply(ident, function, args)?);
+            }
+            _ => unreachable!(),
+        },
     }
+    Ok(())
+}
+
+/// Position of `node` among the nodes of `root` in post-order, the order in
+/// which the parser records their spans.
+fn post_order_index(root: &Expr, node: &Expr) -> Option<usize> {
+    let mut index = 0;
+    // Nodes with whether their children have been pushed.
+    let mut stack = vec![(root, false)];
+    while let Some((expr, expanded)) = stack.pop() {
+        if expanded {
+            if std::ptr::eq(expr, node) {
+                return Some(index);
+            }
+            index += 1;
+            continue;
+        }
+        stack.push((expr, true));
+        match expr {
+            Expr::Binary(lhs, _, rhs) => {
+                stack.push((rhs, false));
+                stack.push((lhs, false));
+            }
+            Expr::Unary(_, expr) | Expr::Paren(expr) => stack.push((expr, false)),
+            Expr::Call(_, args) => stack.extend(args.iter().rev().map(|arg| (arg, false))),
+            _ => {}
+        }
+    }
+    None
 }
 
 impl Parse for BigInt {
@@ -81,12 +207,13 @@ impl Parse for BigInt {
                 Token::NumLit(s) => s.parse()?,
                 _ => unreachable!(),
             },
-            _ => Err(ParseError::ExpectedNum)?,
+            _ => Err(ParseErrorKind::ExpectedNum)?,
         })
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -95,8 +222,38 @@ pub enum BinaryOp {
     Rem,
     Pow,
     Assign,
+    /// Added by the embedding application, see [`CustomOp`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(&'static CustomOp),
+}
+
+/// A binary operator added through
+/// [`ParserConfig::operators`](crate::parse::ParserConfig::operators), such as
+/// `//` for integer division.
+///
+/// Expressions using it can't be serialized, and a serialized
+/// [`Environment`](crate::eval::Environment) with user-defined functions using
+/// it fails to load, naming the first such function.
+#[derive(Debug)]
+pub struct CustomOp {
+    /// Text of the operator. A symbol ending in a letter or digit, such as
+    /// `mod`, is only recognized at the end of a word.
+    pub symbol: &'static str,
+    pub precedence: Precedence,
+    /// Whether `a op b op c` is parsed as `a op (b op c)`.
+    pub right_assoc: bool,
+    /// Called with the values of the operands.
+    pub eval: NativeFn,
 }
 
+impl PartialEq for CustomOp {
+    fn eq(&self, other: &Self) -> bool {
+        self.symbol == other.symbol
+    }
+}
+
+impl Eq for CustomOp {}
+
 impl Parse for BinaryOp {
     fn parse(input: &mut TokenStream) -> ParseResult<Self> {
         let op = Self::peek(input)?;
@@ -115,7 +272,8 @@ impl BinaryOp {
             Token::Percent => Self::Rem,
             Token::AstAst => Self::Pow,
             Token::Equal => Self::Assign,
-            _ => Err(ParseError::ExpectedBinary)?,
+            Token::Custom(op) => Self::Custom(op),
+            _ => Err(ParseErrorKind::ExpectedBinary)?,
         })
     }
     pub fn precedence(&self) -> Precedence {
@@ -124,6 +282,7 @@ impl BinaryOp {
             Self::Mul | Self::Div | Self::Rem => Precedence::Multiplicative,
             Self::Pow => Precedence::Exponent,
             Self::Assign => Precedence::Assign,
+            Self::Custom(op) => op.precedence,
         }
     }
     pub fn peek_precedence(input: &TokenStream) -> Option<Precedence> {
@@ -133,7 +292,22 @@ impl BinaryOp {
         }
     }
     pub fn is_right(&self) -> bool {
-        matches!(self, Self::Pow | Self::Assign)
+        match self {
+            Self::Custom(op) => op.right_assoc,
+            _ => matches!(self, Self::Pow | Self::Assign),
+        }
+    }
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Rem => "%",
+            Self::Pow => "**",
+            Self::Assign => "=",
+            Self::Custom(op) => op.symbol,
+        }
     }
 }
 
@@ -144,20 +318,32 @@ pub enum Precedence {
     Additive,
     Multiplicative,
     Exponent,
+    Unary,
+    Primary,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOp {
     Plus,
     Minus,
 }
 
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Plus => "+",
+            Self::Minus => "-",
+        }
+    }
+}
+
 impl Parse for UnaryOp {
     fn parse(input: &mut TokenStream) -> ParseResult<Self> {
         let op = match input.peek()? {
             Token::Plus => UnaryOp::Plus,
             Token::Minus => UnaryOp::Minus,
-            _ => Err(ParseError::ExpectedUnary)?,
+            _ => Err(ParseErrorKind::ExpectedUnary)?,
         };
         input.consume()?;
         Ok(op)
@@ -170,75 +356,341 @@ impl Parse for Expr {
     }
 }
 
+/// Builds a number literal from the text of a [`Token::NumLit`].
+pub(crate) fn num_lit(lit: &str) -> ParseResult<Expr> {
+    #[cfg(feature = "float")]
+    if lit.contains(['.', ',']) {
+        // A decimal comma, see `ParserConfig::decimal_comma`.
+        return Ok(Expr::Float(lit.replace(',', ".")));
+    }
+    Ok(Expr::Int(lit.parse()?))
+}
+
 fn parse_expr(input: &mut TokenStream) -> ParseResult<Expr> {
+    let start = input.span().start;
     let lhs = parse_unary(input)?;
-    parse_rexpr(input, lhs, Precedence::Any)
+    parse_rexpr(input, lhs, start, Precedence::Any)
 }
 
-fn parse_rexpr(input: &mut TokenStream, mut lhs: Expr, base: Precedence) -> ParseResult<Expr> {
+/// Parses the operators following `lhs`, which starts at `start`.
+fn parse_rexpr(
+    input: &mut TokenStream,
+    mut lhs: Expr,
+    start: usize,
+    base: Precedence,
+) -> ParseResult<Expr> {
     while let Some(precedence) = BinaryOp::peek_precedence(input) {
         if precedence < base {
             break;
         }
         let op = input.parse::<BinaryOp>()?;
         let precedence = op.precedence();
+        let rhs_start = input.span().start;
         let mut rhs = parse_unary(input)?;
         while let Some(next) = BinaryOp::peek_precedence(input) {
             if next > precedence || next == precedence && op.is_right() {
-                rhs = parse_rexpr(input, rhs, next)?;
+                rhs = input.nested(|input| parse_rexpr(input, rhs, rhs_start, next))?;
             } else {
                 break;
             }
         }
         lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+        input.mark(start);
     }
     Ok(lhs)
 }
 
 fn parse_unary(input: &mut TokenStream) -> ParseResult<Expr> {
-    let token = input.peek()?;
-    Ok(match token {
-        Token::Plus | Token::Minus => Expr::Unary(input.parse()?, Box::new(parse_unary(input)?)),
-        Token::NumLit(_) => Expr::Int(input.parse()?),
-        Token::LParen => {
+    // Signs are read in a loop rather than by recursion, as they may repeat
+    // any number of times.
+    let mut signs = vec![];
+    while let Ok(Token::Plus | Token::Minus) = input.peek() {
+        let start = input.span().start;
+        signs.push((start, input.parse::<UnaryOp>()?));
+    }
+    let mut expr = input.nested(parse_operand)?;
+    for (start, op) in signs.into_iter().rev() {
+        expr = Expr::Unary(op, Box::new(expr));
+        input.mark(start);
+    }
+    Ok(expr)
+}
+
+/// Parses an operand without signs. Brackets and calls are parsed by separate
+/// functions to keep this frame small, as it is on the stack once per level of
+/// nesting.
+fn parse_operand(input: &mut TokenStream) -> ParseResult<Expr> {
+    let span = input.span();
+    let expr = match input.peek()? {
+        Token::NumLit(lit) => {
+            let expr = num_lit(lit)?;
             input.consume()?;
-            let expr = input.parse()?;
-            if matches!(input.consume()?, Token::RParen) {
-                Expr::Paren(Box::new(expr))
+            expr
+        }
+        token @ (Token::LParen | Token::LBracket | Token::LBrace) => {
+            parse_group(input, token.closer().unwrap())?
+        }
+        Token::VarLit(lit) => {
+            let ident = lit.to_string();
+            input.consume()?;
+            if matches!(input.peek(), Ok(Token::LParen)) {
+                parse_call(input, ident)?
             } else {
-                Err(ParseError::ExpectedRParen)?
+                Expr::Variable(ident)
             }
         }
-        Token::VarLit(_) => {
-            let ident = match input.consume() {
-                Ok(Token::VarLit(lit)) => lit.to_string(),
-                _ => unreachable!(),
-            };
-            let token = input.peek();
-            if matches!(token, Ok(Token::LParen)) {
+        Token::Keyword(keyword) => Err(ParseError::at(
+            ParseErrorKind::Reserved(keyword.as_str()),
+            span,
+        ))?,
+        _ => Err(ParseErrorKind::ExpectedExpr)?,
+    };
+    input.mark(span.start);
+    parse_superscripts(input, expr, span.start)
+}
+
+/// Parses an expression in brackets, from the opening one.
+fn parse_group(input: &mut TokenStream, closer: char) -> ParseResult<Expr> {
+    input.consume()?;
+    let expr = input.parse()?;
+    if input.peek()?.bracket() != Some(closer) {
+        Err(input.error(ParseErrorKind::ExpectedClosing(closer)))?
+    }
+    input.consume()?;
+    Ok(Expr::Paren(Box::new(expr)))
+}
+
+/// Parses the arguments of a call of `ident`, from the opening parenthesis.
+fn parse_call(input: &mut TokenStream, ident: String) -> ParseResult<Expr> {
+    input.consume()?;
+    // A trailing comma is allowed, an empty argument is not.
+    let mut args = vec![];
+    loop {
+        let position = args.len() + 1;
+        match input.peek()? {
+            Token::RParen => {
                 input.consume()?;
-                let mut args = vec![];
-                loop {
-                    match parse_expr(input) {
-                        Ok(expr) => {
-                            args.push(expr);
-                            match input.consume()? {
-                                Token::Comma => {}
-                                Token::RParen => break,
-                                _ => Err(ParseError::ExpectedRParen)?,
-                            }
+                break;
+            }
+            Token::Comma => Err(ParseError::at(
+                ParseErrorKind::EmptyArgument {
+                    ident: ident.clone(),
+                    position,
+                },
+                input.span(),
+            ))?,
+            _ => {}
+        }
+        args.push(parse_expr(input)?);
+        match input.peek()? {
+            Token::Comma => {}
+            Token::RParen => {
+                input.consume()?;
+                break;
+            }
+            _ => Err(input.error(ParseErrorKind::ExpectedArgumentEnd {
+                ident: ident.clone(),
+                position,
+            }))?,
+        }
+        input.consume()?;
+    }
+    Ok(Expr::Call(ident, args))
+}
+
+/// Parses `x²` and `10⁻³` as powers of `base`, which starts at `start`.
+fn parse_superscripts(input: &mut TokenStream, mut base: Expr, start: usize) -> ParseResult<Expr> {
+    while let Ok(Token::Superscript(lit)) = input.peek() {
+        let span = input.span();
+        let lit: String = lit.chars().filter_map(from_superscript).collect();
+        let exponent = lit
+            .parse()
+            .map_err(|err| ParseError::at(ParseErrorKind::from(err), span))?;
+        input.consume()?;
+        input.mark(span.start);
+        base = Expr::Binary(Box::new(base), BinaryOp::Pow, Box::new(Expr::Int(exponent)));
+        input.mark(start);
+    }
+    Ok(base)
+}
+
+/// Constructors for building expressions without the parser.
+impl Expr {
+    pub fn int(n: impl Into<BigInt>) -> Self {
+        Expr::Int(n.into())
+    }
+    pub fn var(ident: impl Into<String>) -> Self {
+        Expr::Variable(ident.into())
+    }
+    pub fn call(ident: impl Into<String>, args: impl IntoIterator<Item = Expr>) -> Self {
+        Expr::Call(ident.into(), args.into_iter().collect())
+    }
+    pub fn binary(lhs: Expr, op: BinaryOp, rhs: Expr) -> Self {
+        Expr::Binary(Box::new(lhs), op, Box::new(rhs))
+    }
+    pub fn unary(op: UnaryOp, expr: Expr) -> Self {
+        Expr::Unary(op, Box::new(expr))
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(lhs: Expr, rhs: Expr) -> Self {
+        Self::binary(lhs, BinaryOp::Add, rhs)
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn sub(lhs: Expr, rhs: Expr) -> Self {
+        Self::binary(lhs, BinaryOp::Sub, rhs)
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn mul(lhs: Expr, rhs: Expr) -> Self {
+        Self::binary(lhs, BinaryOp::Mul, rhs)
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn div(lhs: Expr, rhs: Expr) -> Self {
+        Self::binary(lhs, BinaryOp::Div, rhs)
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn rem(lhs: Expr, rhs: Expr) -> Self {
+        Self::binary(lhs, BinaryOp::Rem, rhs)
+    }
+    pub fn pow(lhs: Expr, rhs: Expr) -> Self {
+        Self::binary(lhs, BinaryOp::Pow, rhs)
+    }
+    /// `ident = value`
+    pub fn assign(ident: impl Into<String>, value: Expr) -> Self {
+        Self::binary(Self::var(ident), BinaryOp::Assign, value)
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn neg(expr: Expr) -> Self {
+        Self::unary(UnaryOp::Minus, expr)
+    }
+}
+
+macro_rules! impl_binary_op {
+    ($($trait:ident::$method:ident => $op:ident),*) => {$(
+        impl ops::$trait for Expr {
+            type Output = Expr;
+
+            fn $method(self, rhs: Expr) -> Expr {
+                Expr::binary(self, BinaryOp::$op, rhs)
+            }
+        }
+    )*};
+}
+
+impl_binary_op!(Add::add => Add, Sub::sub => Sub, Mul::mul => Mul, Div::div => Div, Rem::rem => Rem);
+
+impl ops::Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        Expr::neg(self)
+    }
+}
+
+impl Expr {
+    pub fn precedence(&self) -> Precedence {
+        match self {
+            Expr::Binary(_, op, _) => op.precedence(),
+            Expr::Unary(_, _) => Precedence::Unary,
+            _ => Precedence::Primary,
+        }
+    }
+    /// Displays the expression with every compound operand parenthesized,
+    /// making the parsed grouping explicit.
+    pub fn grouped(&self) -> impl fmt::Display + '_ {
+        Grouped(self)
+    }
+    /// Writes the nodes from a work stack, like dropping.
+    fn fmt_with<'a>(&'a self, f: &mut fmt::Formatter<'_>, grouped: bool) -> fmt::Result {
+        enum Piece<'a> {
+            Text(&'a str),
+            Expr(&'a Expr),
+        }
+        // Pushed in reverse, as the last piece is written first.
+        let operand = |pieces: &mut Vec<Piece<'a>>, expr: &'a Expr, paren: bool| {
+            if paren || grouped && expr.precedence() < Precedence::Primary {
+                pieces.extend([Piece::Text(")"), Piece::Expr(expr), Piece::Text("(")]);
+            } else {
+                pieces.push(Piece::Expr(expr));
+            }
+        };
+        let mut pieces = vec![Piece::Expr(self)];
+        while let Some(piece) = pieces.pop() {
+            let expr = match piece {
+                Piece::Text(text) => {
+                    f.write_str(text)?;
+                    continue;
+                }
+                Piece::Expr(expr) => expr,
+            };
+            match expr {
+                Expr::Int(n) => write!(f, "{}", n)?,
+                #[cfg(feature = "float")]
+                Expr::Float(s) => write!(f, "{}", s)?,
+                Expr::Binary(lhs, op, rhs) => {
+                    let precedence = op.precedence();
+                    let (l, r) = (lhs.precedence(), rhs.precedence());
+                    operand(
+                        &mut pieces,
+                        rhs,
+                        r < precedence || r == precedence && !op.is_right(),
+                    );
+                    pieces.extend([Piece::Text(" "), Piece::Text(op.symbol()), Piece::Text(" ")]);
+                    operand(
+                        &mut pieces,
+                        lhs,
+                        l < precedence || l == precedence && op.is_right(),
+                    );
+                }
+                Expr::Unary(op, operand_expr) => {
+                    operand(
+                        &mut pieces,
+                        operand_expr,
+                        operand_expr.precedence() < Precedence::Unary,
+                    );
+                    pieces.push(Piece::Text(op.symbol()));
+                }
+                Expr::Paren(expr) => {
+                    pieces.extend([Piece::Text(")"), Piece::Expr(expr), Piece::Text("(")])
+                }
+                Expr::Variable(ident) => f.write_str(ident)?,
+                Expr::Call(ident, args) => {
+                    pieces.push(Piece::Text(")"));
+                    for (i, arg) in args.iter().enumerate().rev() {
+                        pieces.push(Piece::Expr(arg));
+                        if i > 0 {
+                            pieces.push(Piece::Text(", "));
                         }
-                        Err(_) => match input.consume()? {
-                            Token::RParen => break,
-                            _ => Err(ParseError::ExpectedRParen)?,
-                        },
                     }
+                    pieces.extend([Piece::Text("("), Piece::Text(ident)]);
                 }
-                Expr::Call(ident, args)
-            } else {
-                Expr::Variable(ident)
             }
         }
-        _ => Err(ParseError::ExpectedUnary)?,
-    })
+        Ok(())
+    }
+    /// Moves the children of the node to `stack`, leaving placeholders.
+    fn take_children(&mut self, stack: &mut Vec<Expr>) {
+        match self {
+            Expr::Binary(lhs, _, rhs) => {
+                stack.push(mem::take(&mut **lhs));
+                stack.push(mem::take(&mut **rhs));
+            }
+            Expr::Unary(_, expr) | Expr::Paren(expr) => stack.push(mem::take(&mut **expr)),
+            Expr::Call(_, args) => stack.append(args),
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, false)
+    }
+}
+
+struct Grouped<'a>(&'a Expr);
+
+impl fmt::Display for Grouped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_with(f, true)
+    }
 }
@@ -0,0 +1,300 @@
+use crate::value::Value;
+#[cfg(feature = "float")]
+use num::{
+    pow::Pow,
+    rational::{BigRational, Ratio},
+    BigInt, One,
+};
+use std::str::FromStr;
+
+/// Significant digits of [`Notation::Fraction`] unless set, fewer than floats
+/// have at the default precision so that their rounding error is ignored.
+#[cfg(feature = "float")]
+const FRACTION_DIGITS: usize = 30;
+
+/// Exponents beyond this are written in scientific notation even in
+/// [`Notation::Plain`] and [`Notation::Fraction`], rather than as that many
+/// zeros.
+#[cfg(feature = "float")]
+const MAX_PLAIN_EXP: i64 = 1000;
+
+/// Settings shared by every frontend for rendering results.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Formatter {
+    /// Results longer than this many characters are truncated, keeping the
+    /// beginning and the end and noting how many characters were omitted.
+    pub max_length: Option<usize>,
+    /// Separates the integer digits of numbers into groups, e.g. `1,234,567`.
+    pub grouping: Option<DigitGrouping>,
+    /// Base to write integers in, from 2 to 36, if not 10. Digits above 9
+    /// are written `a` to `z`. Other bases are ignored.
+    pub radix: Option<u32>,
+    /// How numbers that aren't integers are written.
+    pub notation: Notation,
+    /// Numbers that aren't integers are rounded to this many significant
+    /// digits.
+    pub significant_digits: Option<usize>,
+}
+
+/// How [`Formatter`] writes numbers that aren't integers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Notation {
+    /// `1.5e+3`
+    #[default]
+    Scientific,
+    /// `1500`
+    Plain,
+    /// Scientific with an exponent that is a multiple of 3, e.g. `15e+3`.
+    Engineering,
+    /// `3/2`, the simplest fraction that rounds to the shown digits.
+    Fraction,
+}
+
+impl FromStr for Notation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "scientific" => Notation::Scientific,
+            "plain" => Notation::Plain,
+            "engineering" => Notation::Engineering,
+            "fraction" => Notation::Fraction,
+            _ => Err(format!("unknown notation `{}`", s))?,
+        })
+    }
+}
+
+/// How [`Formatter`] groups digits.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DigitGrouping {
+    /// Number of digits in each group, counted from the decimal point.
+    pub size: usize,
+    pub separator: char,
+}
+
+impl Default for DigitGrouping {
+    fn default() -> Self {
+        Self {
+            size: 3,
+            separator: ',',
+        }
+    }
+}
+
+impl Formatter {
+    pub fn format(&self, value: &Value) -> String {
+        let radix = self.radix.filter(|radix| (2..=36).contains(radix));
+        let s = match (value, radix) {
+            (Value::Int(n), Some(radix)) => n.to_str_radix(radix),
+            #[cfg(feature = "float")]
+            (Value::Float(_), _) => self.format_float(value.to_string()),
+            _ => value.to_string(),
+        };
+        let s = match (self.grouping, value) {
+            (_, Value::Str(_)) | (None, _) => s,
+            (Some(grouping), Value::Int(_)) => {
+                grouping.apply(&s, radix.is_some_and(|radix| radix > 10))
+            }
+            #[cfg(feature = "float")]
+            (Some(grouping), Value::Float(_)) => grouping.apply(&s, false),
+        };
+        self.truncate(s)
+    }
+    /// Rewrites a float displayed as `d.ddde±N`.
+    #[cfg(feature = "float")]
+    fn format_float(&self, s: String) -> String {
+        let digits = match self.notation {
+            Notation::Fraction => self.significant_digits.or(Some(FRACTION_DIGITS)),
+            _ => self.significant_digits,
+        };
+        let mut decimal = match Decimal::parse(&s) {
+            Some(decimal) if self.notation != Notation::Scientific || digits.is_some() => decimal,
+            _ => return s,
+        };
+        if let Some(digits) = digits {
+            decimal.round(digits);
+        }
+        match self.notation {
+            _ if decimal.exp.abs() > MAX_PLAIN_EXP => decimal.with_exponent(decimal.exp),
+            Notation::Scientific => decimal.with_exponent(decimal.exp),
+            Notation::Engineering => decimal.with_exponent(decimal.exp.div_euclid(3) * 3),
+            Notation::Plain => decimal.plain(),
+            Notation::Fraction => decimal.fraction(),
+        }
+    }
+    fn truncate(&self, s: String) -> String {
+        let len = s.chars().count();
+        match self.max_length {
+            Some(max_length) if len > max_length => {
+                let head = max_length - max_length / 2;
+                let tail = max_length / 2;
+                let head_end = s.char_indices().nth(head).map_or(s.len(), |(i, _)| i);
+                let tail_start = s.char_indices().nth(len - tail).map_or(s.len(), |(i, _)| i);
+                format!(
+                    "{}…({} more)…{}",
+                    &s[..head_end],
+                    len - max_length,
+                    &s[tail_start..]
+                )
+            }
+            _ => s,
+        }
+    }
+}
+
+impl DigitGrouping {
+    /// Groups the first run of digits in `s`, the integer part of a number,
+    /// which ends at a decimal point or an exponent such as `e+3`. `letters`
+    /// counts letters as digits, for integers in bases above 10.
+    fn apply(&self, s: &str, letters: bool) -> String {
+        if self.size == 0 {
+            return s.to_string();
+        }
+        let is_digit = |c: char| c.is_ascii_digit() || letters && c.is_ascii_alphabetic();
+        let start = s.find(is_digit).unwrap_or(s.len());
+        let len = s[start..]
+            .find(|c: char| !is_digit(c))
+            .unwrap_or(s.len() - start);
+        let mut out = String::with_capacity(s.len() + len / self.size);
+        out.push_str(&s[..start]);
+        for (i, c) in s[start..start + len].chars().enumerate() {
+            if i > 0 && (len - i) % self.size == 0 {
+                out.push(self.separator);
+            }
+            out.push(c);
+        }
+        out.push_str(&s[start + len..]);
+        out
+    }
+}
+
+/// A nonzero number `±d.ddd × 10^exp`.
+#[cfg(feature = "float")]
+struct Decimal {
+    negative: bool,
+    /// Significant digits without trailing zeros, the first one nonzero.
+    digits: String,
+    exp: i64,
+    /// Number of significant digits it was rounded to, including trailing
+    /// zeros. Floats don't display their trailing zeros, so this is only known
+    /// after [`Decimal::round`].
+    precision: usize,
+}
+
+#[cfg(feature = "float")]
+impl Decimal {
+    fn parse(s: &str) -> Option<Self> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let (mantissa, exp) = s.split_once('e')?;
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits = format!("{}{}", int, frac).trim_end_matches('0').to_string();
+        let valid = int.len() == 1 && digits.bytes().all(|b| b.is_ascii_digit());
+        if !valid || !digits.starts_with(|c| c != '0') {
+            return None;
+        }
+        Some(Self {
+            negative,
+            precision: digits.len(),
+            digits,
+            exp: exp.parse().ok()?,
+        })
+    }
+    /// Rounds to `n` significant digits, halves away from zero.
+    fn round(&mut self, n: usize) {
+        let n = n.max(1);
+        self.precision = n;
+        if self.digits.len() <= n {
+            return;
+        }
+        let round_up = self.digits.as_bytes()[n] >= b'5';
+        let mut digits = self.digits.as_bytes()[..n].to_vec();
+        if round_up {
+            match digits.iter().rposition(|&d| d != b'9') {
+                Some(i) => {
+                    digits[i] += 1;
+                    digits.truncate(i + 1);
+                }
+                None => {
+                    digits = vec![b'1'];
+                    self.exp += 1;
+                }
+            }
+        }
+        let digits = String::from_utf8(digits).unwrap();
+        self.digits = digits.trim_end_matches('0').to_string();
+    }
+    fn sign(&self) -> &'static str {
+        if self.negative {
+            "-"
+        } else {
+            ""
+        }
+    }
+    /// The digits with the point after the digit for `10^exp` and no point
+    /// if nothing follows it. `exp` must not exceed `self.exp`.
+    fn point_at(&self, exp: i64) -> String {
+        let int_len = (self.exp - exp + 1) as usize;
+        let mut digits = self.digits.clone();
+        while digits.len() < int_len {
+            digits.push('0');
+        }
+        match digits.split_at(int_len) {
+            (int, "") => int.to_string(),
+            (int, frac) => format!("{}.{}", int, frac),
+        }
+    }
+    fn with_exponent(&self, exp: i64) -> String {
+        format!("{}{}e{:+}", self.sign(), self.point_at(exp), exp)
+    }
+    fn plain(&self) -> String {
+        if self.exp < 0 {
+            let zeros = "0".repeat((-self.exp - 1) as usize);
+            format!("{}0.{}{}", self.sign(), zeros, self.digits)
+        } else {
+            format!("{}{}", self.sign(), self.point_at(0))
+        }
+    }
+    fn fraction(&self) -> String {
+        let ten = Ratio::from(BigInt::from(10));
+        let n: BigInt = self.digits.parse().unwrap();
+        let x = Ratio::from(n)
+            * ten
+                .clone()
+                .pow((self.exp + 1) as i32 - self.digits.len() as i32);
+        // Any number that rounds to the known digits.
+        let half = ten.pow((self.exp + 1) as i32 - self.precision as i32) / BigInt::from(2);
+        let r = if x.is_integer() {
+            x
+        } else {
+            simplest(&(&x - &half), &(&x + &half))
+        };
+        if r.denom().is_one() {
+            format!("{}{}", self.sign(), r.numer())
+        } else {
+            format!("{}{}/{}", self.sign(), r.numer(), r.denom())
+        }
+    }
+}
+
+/// The fraction with the smallest denominator in `lo..=hi`, for `0 < lo`.
+#[cfg(feature = "float")]
+fn simplest(lo: &BigRational, hi: &BigRational) -> BigRational {
+    let floor = lo.floor();
+    if &floor == lo {
+        return floor;
+    }
+    let next = &floor + BigInt::one();
+    if &next <= hi {
+        return next;
+    }
+    let rest = simplest(&(hi - &floor).recip(), &(lo - &floor).recip());
+    floor + rest.recip()
+}
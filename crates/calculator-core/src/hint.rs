@@ -0,0 +1,57 @@
+use crate::{
+    expr::{BinaryOp, Expr},
+    stmt::Stmt,
+    visit::ExprVisitor,
+};
+use std::fmt;
+
+/// A subexpression mixing operators whose relative precedence is commonly
+/// misread, such as `-2 ** 2`, `a / b * c` and `x ** y ** z`.
+#[derive(Debug, Clone)]
+pub struct Hint {
+    pub source: String,
+    pub grouped: String,
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` is parsed as `{}`", self.source, self.grouped)
+    }
+}
+
+pub fn hints(stmt: &Stmt) -> Vec<Hint> {
+    let mut collector = Collector(Vec::new());
+    match stmt {
+        Stmt::Expr(expr) => collector.visit_expr(expr),
+        Stmt::Define { body, .. } => collector.visit_expr(body),
+    }
+    collector.0
+}
+
+fn is_ambiguous(expr: &Expr) -> bool {
+    use BinaryOp::*;
+    match expr {
+        Expr::Binary(lhs, op, rhs) => matches!(
+            (&**lhs, op, &**rhs),
+            (Expr::Unary(_, _), Pow, _)
+                | (Expr::Binary(_, Div | Rem, _), Mul | Div | Rem, _)
+                | (_, Pow, Expr::Binary(_, Pow, _))
+        ),
+        _ => false,
+    }
+}
+
+struct Collector(Vec<Hint>);
+
+impl ExprVisitor for Collector {
+    fn enter(&mut self, expr: &Expr) -> bool {
+        if is_ambiguous(expr) {
+            self.0.push(Hint {
+                source: expr.to_string(),
+                grouped: expr.grouped().to_string(),
+            });
+            return false;
+        }
+        true
+    }
+}
@@ -0,0 +1,43 @@
+use crate::value::Value;
+
+/// An input of a session and its result.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entry {
+    pub input: String,
+    /// `None` if evaluation failed or the input had no value.
+    pub result: Option<Value>,
+}
+
+/// Inputs of a session, numbered from 1.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct History {
+    entries: Vec<Entry>,
+}
+
+impl History {
+    pub fn push(&mut self, input: String, result: Option<Value>) {
+        self.entries.push(Entry { input, result });
+    }
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+    /// Keeps only the first `len` entries.
+    pub fn truncate(&mut self, len: usize) {
+        self.entries.truncate(len);
+    }
+    /// Returns the `n`-th entry, counting from 1.
+    pub fn get(&self, n: usize) -> Option<&Entry> {
+        n.checked_sub(1).and_then(|i| self.entries.get(i))
+    }
+    /// Finds the entries whose result equals `value`, with their numbers.
+    pub fn whence<'a>(&'a self, value: &'a Value) -> impl Iterator<Item = (usize, &'a Entry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(move |(_, entry)| entry.result.as_ref() == Some(value))
+            .map(|(i, entry)| (i + 1, entry))
+    }
+}
@@ -1,4 +1,19 @@
+pub mod ast;
+pub mod builtins;
+pub mod diagnostic;
 pub mod eval;
 pub mod expr;
+pub mod format;
+pub mod hint;
+pub mod history;
+pub mod math;
+pub mod mathml;
+mod memo;
+pub mod normalize;
+pub mod optimize;
 pub mod parse;
+pub mod simplify;
+pub mod stmt;
 pub mod token;
+pub mod value;
+pub mod visit;
@@ -1,10 +1,37 @@
+use anyhow::{anyhow, bail, Context};
 use calculator_core::{
-    eval::{Environment, Eval},
-    expr::Expr,
-    parse::parse_from_str,
+    builtins::BUILTINS,
+    diagnostic::Diagnostic,
+    eval::{CancelToken, Environment, Eval, EvalOptions, Function},
+    format::{DigitGrouping, Formatter, Notation},
+    hint::hints,
+    history::History,
+    parse::ParserConfig,
+    stmt::Stmt,
+    token::Span,
+    value::Value,
 };
-use clap::Parser;
-use std::io::{stdin, stdout, Write};
+use clap::{ColorChoice, Parser};
+use num::bigint::Sign;
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    validate::Validator, Editor, Helper,
+};
+use serde_json::json;
+use std::{
+    env, fmt, fs,
+    io::{self, stdin, IsTerminal},
+    mem,
+    path::{Path, PathBuf},
+    process::{self, ExitCode},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use unicode_ident::is_xid_continue;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -12,30 +39,857 @@ struct Args {
     /// Use verbose output
     #[arg(short, long)]
     verbose: bool,
+    /// Truncate results longer than this many characters
+    #[arg(long, value_name = "N")]
+    max_length: Option<usize>,
+    /// Separate the digits of results into groups of N, e.g. `1,234,567`
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "3")]
+    group_digits: Option<usize>,
+    /// Print integer results in base N, from 2 to 36
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(2..=36))]
+    obase: Option<u32>,
+    /// Round non-integer results to N significant digits
+    #[arg(long, value_name = "N")]
+    precision: Option<usize>,
+    /// Write non-integer results as `plain` (1500), `scientific` (1.5e+3),
+    /// `engineering` (1.5e+3 with exponents divisible by 3) or `fraction` (3/2)
+    #[arg(long, value_name = "NOTATION", default_value = "scientific")]
+    format: Notation,
+    /// Print the parse tree of each input as an S-expression
+    #[arg(long)]
+    ast: bool,
+    /// Print how long parsing and evaluating each input took
+    #[arg(long)]
+    time: bool,
+    /// Show how operators with easily confused precedence were grouped
+    #[arg(long)]
+    hints: bool,
+    /// Read input in reverse Polish notation, e.g. `3 4 + 5 *`
+    #[arg(long)]
+    rpn: bool,
+    /// Read `3,14` as a decimal number and separate arguments with `;`
+    #[arg(long)]
+    decimal_comma: bool,
+    /// Evaluate EXPR, print the result and exit instead of starting a prompt;
+    /// may be repeated
+    #[arg(short = 'e', long = "eval", value_name = "EXPR")]
+    exprs: Vec<String>,
+    /// Print a JSON object `{"input": ..., "result": ..., "error": ...}` for
+    /// every evaluated line instead of the plain result
+    #[arg(long)]
+    json: bool,
+    /// Print exactly one line for each input line, without a prompt: the
+    /// result, with its lines joined by `; `, or an empty line if there is
+    /// none, with only the first line of errors on stderr
+    #[arg(short, long)]
+    quiet: bool,
+    /// Continue after inputs that fail when not at the prompt, exiting with
+    /// the status of the worst failure: 1 for syntax errors, 2 for others
+    #[arg(long)]
+    keep_going: bool,
+    /// Evaluate the lines of FILE quietly on startup [default: ~/.calcrc]
+    #[arg(long, value_name = "FILE")]
+    init: Option<PathBuf>,
+    /// Color the output: `auto` does so on terminals unless NO_COLOR is set
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
+    /// Keep the input history of the prompt in FILE [default:
+    /// ~/.calculator_history]
+    #[arg(long, value_name = "FILE")]
+    history_file: Option<PathBuf>,
+    /// Evaluate the lines of FILE again whenever it changes, printing the new
+    /// results
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["exprs", "file"])]
+    watch: Option<PathBuf>,
+    /// Evaluate the lines of FILE, printing each result, and exit; runs
+    /// before any -e
+    file: Option<PathBuf>,
+}
+
+struct Session {
+    args: Args,
+    env: Environment,
+    formatter: Formatter,
+    parser: ParserConfig,
+    history: History,
+    /// Evaluate without printing results or recording history, for the init
+    /// file.
+    silent: bool,
+    /// Print how long parsing and evaluation took.
+    time: bool,
+    /// Cancels the evaluation in progress on Ctrl-C.
+    cancel_token: CancelToken,
+    /// Whether an evaluation is in progress. Ctrl-C exits otherwise.
+    evaluating: Arc<AtomicBool>,
+    out_style: Style,
+    err_style: Style,
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-    let mut input = String::new();
-    let mut env = Environment::default();
-    loop {
-        input.clear();
-        print!("> ");
-        stdout().flush()?;
-        stdin().read_line(&mut input)?;
-        let expr: Expr = match parse_from_str(&input) {
-            Ok(expr) => expr,
+impl Session {
+    fn new(args: Args) -> Self {
+        let formatter = Formatter {
+            max_length: args.max_length,
+            grouping: args.group_digits.map(|size| DigitGrouping {
+                size,
+                ..Default::default()
+            }),
+            radix: args.obase.filter(|&radix| radix != 10),
+            notation: args.format,
+            significant_digits: args.precision,
+        };
+        let parser = ParserConfig {
+            decimal_comma: args.decimal_comma,
+            ..Default::default()
+        };
+        Self {
+            time: args.time,
+            out_style: Style::new(args.color, io::stdout().is_terminal() && !args.quiet),
+            err_style: Style::new(args.color, io::stderr().is_terminal()),
+            args,
+            env: Environment::default(),
+            formatter,
+            parser,
+            history: History::default(),
+            silent: false,
+            cancel_token: CancelToken::new(),
+            evaluating: Arc::new(AtomicBool::new(false)),
+        }
+    }
+    /// Makes Ctrl-C cancel the evaluation in progress, or exit if there is
+    /// none.
+    fn handle_interrupt(&self) -> anyhow::Result<()> {
+        let cancel_token = self.cancel_token.clone();
+        let evaluating = self.evaluating.clone();
+        ctrlc::set_handler(move || {
+            if evaluating.load(Ordering::SeqCst) {
+                cancel_token.cancel();
+            } else {
+                process::exit(130);
+            }
+        })?;
+        Ok(())
+    }
+    fn print_error(&self, err: &anyhow::Error) {
+        if !err.is::<Reported>() {
+            self.report(&err.to_string());
+        }
+    }
+    /// Prints an error message, only its first line with `--quiet`.
+    fn report(&self, message: &str) {
+        let message = if self.args.quiet {
+            message.lines().next().unwrap_or_default()
+        } else {
+            message
+        };
+        eprintln!("{}", self.err_style.error(message));
+    }
+    fn run_line(&mut self, line: &str) -> anyhow::Result<()> {
+        let result = self.run_trimmed(line.trim());
+        match &result {
+            // Keeps the output aligned with the input.
+            Err(err) if self.args.quiet && !self.silent && !err.is::<Reported>() => println!(),
+            _ => {}
+        }
+        result
+    }
+    fn run_trimmed(&mut self, line: &str) -> anyhow::Result<()> {
+        if line.is_empty() {
+            if self.args.quiet && !self.silent {
+                println!();
+            }
+            return Ok(());
+        }
+        let expanded = self.expand_history(line)?;
+        if expanded != line && !self.silent && !self.args.quiet {
+            eprintln!("{}", self.err_style.paint(INPUT_COLOR, &expanded));
+        }
+        let line = expanded.as_str();
+        match line.strip_prefix(':') {
+            Some(command) => self.run_command(command),
+            None => self.eval(line),
+        }
+    }
+    /// Replaces `!!` in `line` with the previous input and `!n` with the
+    /// `n`-th input of the session, as numbered by `:whence`.
+    fn expand_history(&self, line: &str) -> anyhow::Result<String> {
+        let mut output = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(pos) = rest.find('!') {
+            output.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+            let len = if rest.starts_with('!') {
+                1
+            } else {
+                rest.find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len())
+            };
+            if len == 0 {
+                output.push('!');
+                continue;
+            }
+            let entry = match &rest[..len] {
+                "!" => self.history.entries().last(),
+                n => n.parse().ok().and_then(|n| self.history.get(n)),
+            };
+            let entry =
+                entry.ok_or_else(|| anyhow!("no input `!{}` in the history", &rest[..len]))?;
+            output.push_str(&entry.input);
+            rest = &rest[len..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+    /// Whether `input` ends inside brackets, so that the next line should be
+    /// appended to it rather than reporting an error.
+    fn is_incomplete(&self, input: &str) -> bool {
+        if self.args.rpn || self.args.quiet || input.trim_start().starts_with(':') {
+            return false;
+        }
+        let Ok(input) = expand_env_vars(input) else {
+            return false;
+        };
+        matches!(self.parser.parse_with_spans::<Stmt>(&input), Err(err) if err.is_incomplete())
+    }
+    /// Joins each line of `input` that is incomplete with the lines after it,
+    /// returning the inputs with the index of their first line.
+    fn join_continued(&self, input: &str) -> Vec<(usize, String)> {
+        let mut inputs: Vec<(usize, String)> = Vec::new();
+        let mut continued = false;
+        for (n, line) in input.lines().enumerate() {
+            match inputs.last_mut() {
+                Some((_, input)) if continued => {
+                    input.push('\n');
+                    input.push_str(line);
+                }
+                _ => inputs.push((n, line.to_string())),
+            }
+            continued = self.is_incomplete(&inputs.last().unwrap().1);
+        }
+        inputs
+    }
+    /// Runs the init file quietly, if there is one. Like any script it stops
+    /// at its first error, which is reported, but the session still starts.
+    /// Only a `required` file that can't be read is an error; the default one
+    /// is skipped with a warning.
+    fn run_init(&mut self, path: &Path, required: bool) -> anyhow::Result<()> {
+        let input = match fs::read_to_string(path) {
+            Ok(input) => input,
+            Err(err) if required => {
+                Err(err).with_context(|| format!("failed to read `{}`", path.display()))?
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
             Err(err) => {
-                eprintln!("error: {}", err);
+                eprintln!("warning: failed to read `{}`: {}", path.display(), err);
+                return Ok(());
+            }
+        };
+        self.silent = true;
+        self.run_script(Some(path), &input);
+        self.silent = false;
+        Ok(())
+    }
+    /// Runs each line of `input`, from the file `path` if any, stopping at the
+    /// first error since later lines may depend on it, unless `--keep-going`.
+    /// Returns the [`exit_status`] of the worst error, or 0.
+    fn run_script(&mut self, path: Option<&Path>, input: &str) -> u8 {
+        let mut status = 0;
+        for (n, line) in self.join_continued(input) {
+            if let Err(err) = self.run_line(&line) {
+                match path {
+                    _ if err.is::<Reported>() => {}
+                    Some(path) => self.report(&format!("{}:{}: {}", path.display(), n + 1, err)),
+                    None => self.print_error(&err),
+                }
+                status = status.max(exit_status(&err));
+                if !self.args.keep_going {
+                    break;
+                }
+            }
+        }
+        status
+    }
+    /// Runs the script at `path` whenever it is modified, each time starting
+    /// over from the variables and functions defined before.
+    fn watch(&mut self, path: &Path) -> anyhow::Result<()> {
+        fs::metadata(path).with_context(|| format!("failed to read `{}`", path.display()))?;
+        let start = self.env.snapshot();
+        let mut modified = None;
+        loop {
+            // Editors may save by replacing the file, so that it is missing for
+            // a moment.
+            let current = fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            if let Some(current) = current.filter(|&current| Some(current) != modified) {
+                if let Ok(input) = fs::read_to_string(path) {
+                    modified = Some(current);
+                    self.env.restore(start.clone());
+                    self.history = History::default();
+                    if io::stdout().is_terminal() {
+                        // Clears the screen for the new results.
+                        print!("\x1b[2J\x1b[H");
+                    }
+                    self.run_script(Some(path), &input);
+                }
+            }
+            thread::sleep(WATCH_INTERVAL);
+        }
+    }
+    fn eval(&mut self, input: &str) -> anyhow::Result<()> {
+        let result = self.eval_stmt(input);
+        if self.silent {
+            return result.map(drop);
+        }
+        self.history
+            .push(input.to_string(), result.as_ref().ok().cloned().flatten());
+        if self.args.json {
+            let (value, error) = match &result {
+                Ok(value) => (
+                    value.as_ref().map(|value| self.formatter.format(value)),
+                    None,
+                ),
+                Err(err) => (None, Some(err.to_string())),
+            };
+            let output = json!({ "input": input, "result": value, "error": error });
+            println!("{}", output);
+            return result
+                .map(drop)
+                .map_err(|err| Reported(exit_status(&err)).into());
+        }
+        match &result {
+            Ok(Some(value)) => {
+                let mut value = self.formatter.format(value);
+                if self.args.quiet {
+                    value = value.lines().collect::<Vec<_>>().join("; ");
+                }
+                println!("{}", self.out_style.paint(RESULT_COLOR, &value));
+            }
+            // Keeps the output aligned with the input.
+            Ok(None) if self.args.quiet => println!(),
+            _ => {}
+        }
+        result.map(drop)
+    }
+    /// Parses `input`, with environment variables already expanded.
+    fn parse(&self, input: &str) -> anyhow::Result<(Stmt, Vec<Span>)> {
+        let parsed = if self.args.rpn {
+            self.parser
+                .parse_rpn_with_spans(input)
+                .map(|(expr, spans)| (Stmt::Expr(expr), spans))
+        } else {
+            self.parser.parse_with_spans(input)
+        };
+        parsed.map_err(|err| {
+            // Show all syntax errors of the line, not only the first one. The
+            // script parser would take continued lines for separate inputs.
+            let diagnostics = match self.parser.parse_script(input) {
+                Err(diagnostics) if !self.args.rpn && !input.contains('\n') => diagnostics,
+                _ => vec![Diagnostic::new(&err, err.span)],
+            };
+            let rendered: Vec<_> = diagnostics.iter().map(|d| d.render(input)).collect();
+            SyntaxError(rendered.join("\nerror: ")).into()
+        })
+    }
+    fn eval_stmt(&mut self, input: &str) -> anyhow::Result<Option<Value>> {
+        let input = expand_env_vars(input)?;
+        let start = Instant::now();
+        let (stmt, spans) = self.parse(&input)?;
+        let parsed = Instant::now();
+        if self.args.ast {
+            eprintln!("ast: {}", stmt.to_sexpr());
+        }
+        if self.args.verbose {
+            eprintln!("stmt = {:?}", stmt);
+        }
+        if self.args.hints {
+            for hint in hints(&stmt) {
+                eprintln!("hint: {}", hint);
+            }
+        }
+        self.cancel_token.reset();
+        let options = EvalOptions::default().with_cancel_token(self.cancel_token.clone());
+        self.evaluating.store(true, Ordering::SeqCst);
+        let result = self.env.transaction(|env| stmt.eval_with(env, options));
+        self.evaluating.store(false, Ordering::SeqCst);
+        if self.time {
+            let size = match &result {
+                Ok(Some(value)) => format!(", result {} bytes", value.size_hint()),
+                _ => String::new(),
+            };
+            eprintln!(
+                "time: parse {:?}, eval {:?}{}",
+                parsed - start,
+                parsed.elapsed(),
+                size
+            );
+        }
+        result.map_err(|err| anyhow!("{}", Diagnostic::new(&err, err.span(&spans)).render(&input)))
+    }
+    /// Names of the variables and functions in scope, for completion.
+    fn names(&self) -> Vec<String> {
+        let variables = self.env.variables().map(|(ident, _)| ident.to_string());
+        let functions = (self.env.functions().map(|(ident, _)| ident))
+            .chain(BUILTINS.iter().map(|builtin| builtin.name))
+            .map(|ident| format!("{}(", ident));
+        let mut names: Vec<_> = variables.chain(functions).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+    fn run_command(&mut self, command: &str) -> anyhow::Result<()> {
+        let (name, arg) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let mut lines = Vec::new();
+        match name {
+            "whence" => {
+                let value = match self.eval_stmt(arg)? {
+                    Some(value) => value,
+                    None => bail!("expected an expression"),
+                };
+                let mut found = false;
+                for (n, entry) in self.history.whence(&value) {
+                    lines.push(format!("[{}] {}", n, entry.input));
+                    found = true;
+                }
+                if !found {
+                    lines.push(format!(
+                        "no input evaluated to {}",
+                        self.formatter.format(&value)
+                    ));
+                }
+            }
+            "type" => match self.eval_stmt(arg)? {
+                Some(value) => lines.push(value.type_name().to_string()),
+                None => bail!("expected an expression"),
+            },
+            // Names of functions, which can't be evaluated on their own.
+            "info" if self.env.get_variable(arg).is_err() && self.env.get_function(arg).is_ok() => {
+                let function = self.env.get_function(arg)?;
+                let kind = match &function {
+                    Function::Native(_) => "builtin function",
+                    Function::Host(_) => "host function",
+                    Function::User { .. } => "function",
+                };
+                lines.push(format!("type: {}", kind));
+                match function.arity() {
+                    Some(arity) => lines.push(format!("arity: {}", arity)),
+                    None => lines.push("arity: variadic".to_string()),
+                }
+                if let Some(params) = function.params() {
+                    lines.push(format!("params: {}", params.join(", ")));
+                }
+                if let Some(body) = function.body() {
+                    lines.push(format!("body: {}", body));
+                }
+            }
+            "info" => {
+                let value = match self.eval_stmt(arg)? {
+                    Some(value) => value,
+                    None => bail!("expected an expression"),
+                };
+                lines.push(format!("type: {}", value.type_name()));
+                if let Some(bits) = value.bits() {
+                    lines.push(format!("bits: {}", bits));
+                }
+                if let Some(sign) = value.sign() {
+                    let sign = match sign {
+                        Sign::Minus => "negative",
+                        Sign::NoSign => "zero",
+                        Sign::Plus => "positive",
+                    };
+                    lines.push(format!("sign: {}", sign));
+                }
+            }
+            "ast" => {
+                let (stmt, _) = self.parse(&expand_env_vars(arg)?)?;
+                lines.push(stmt.to_sexpr());
+            }
+            "save" if !arg.is_empty() => {
+                let json = serde_json::to_string_pretty(&self.env)?;
+                fs::write(arg, json).with_context(|| format!("failed to write `{}`", arg))?;
+            }
+            "load" if !arg.is_empty() => {
+                let json =
+                    fs::read_to_string(arg).with_context(|| format!("failed to read `{}`", arg))?;
+                self.env = serde_json::from_str(&json)
+                    .with_context(|| format!("`{}` is not a saved session", arg))?;
+            }
+            "save" | "load" => bail!("expected a file name"),
+            "time" if arg.is_empty() => {
+                self.time = !self.time;
+                lines.push(format!("timing {}", if self.time { "on" } else { "off" }));
+            }
+            "time" => {
+                let time = mem::replace(&mut self.time, true);
+                let result = self.eval(arg);
+                self.time = time;
+                // The result is already printed.
+                return result;
+            }
+            "clear" => {
+                if io::stdout().is_terminal() {
+                    print!("\x1b[2J\x1b[H");
+                }
+            }
+            "reset" => self.env = Environment::default(),
+            "obase" if arg.is_empty() => {
+                lines.push(self.formatter.radix.unwrap_or(10).to_string());
+            }
+            "obase" => match arg.trim().parse() {
+                Ok(radix @ 2..=36) => self.formatter.radix = Some(radix).filter(|&r| r != 10),
+                _ => bail!("expected a base from 2 to 36"),
+            },
+            "help" if arg.is_empty() => {
+                lines.extend(COMMANDS_HELP.lines().map(str::to_string));
+                let builtins: Vec<_> = BUILTINS.iter().map(|builtin| builtin.name).collect();
+                lines.push(format!("builtin functions: {}", builtins.join(", ")));
+            }
+            "help" => {
+                let function = self.env.get_function(arg)?;
+                match (&function, function.params()) {
+                    (Function::User { params, body, .. }, _) => {
+                        lines.push(format!("{}({}) = {}", arg, params.join(", "), body))
+                    }
+                    (_, Some(params)) => lines.push(format!("{}({})", arg, params.join(", "))),
+                    (_, None) => lines.push(format!("{}(...)", arg)),
+                }
+                if let Some(doc) = function.doc() {
+                    lines.push(format!("    {}", doc));
+                }
+            }
+            _ => bail!("unknown command `:{}`", name),
+        }
+        self.print_lines(&lines);
+        Ok(())
+    }
+    /// Prints the output of a command, joined into one line with `--quiet`
+    /// like multi-line results, or an empty line if there is none.
+    fn print_lines(&self, lines: &[String]) {
+        if self.args.quiet {
+            if !self.silent {
+                println!("{}", lines.join("; "));
+            }
+        } else {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    let mut args = Args::parse();
+    let exprs = mem::take(&mut args.exprs);
+    let file = args.file.take();
+    let watch = args.watch.take();
+    let history_file = args
+        .history_file
+        .take()
+        .or_else(|| env::home_dir().map(|home| home.join(".calculator_history")));
+    let init = match args.init.take() {
+        Some(path) => Some((path, true)),
+        None => env::home_dir().map(|home| (home.join(".calcrc"), false)),
+    };
+    // Only prompt when a user is typing, not for `echo 2+2 | calculator`.
+    let interactive = file.is_none()
+        && exprs.is_empty()
+        && watch.is_none()
+        && !args.quiet
+        && stdin().is_terminal();
+    // The editor replaces the SIGINT handler when created, so it has to come
+    // before ours.
+    let editor = interactive.then(Editor::new).transpose()?;
+    let mut session = Session::new(args);
+    session.handle_interrupt()?;
+    if let Some((path, required)) = init {
+        session.run_init(&path, required)?;
+    }
+    if let Some(path) = &watch {
+        session.watch(path)?;
+    }
+    let keep_going = session.args.keep_going;
+    if file.is_some() || !exprs.is_empty() {
+        let mut status = match &file {
+            Some(path) => {
+                let input = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read `{}`", path.display()))?;
+                session.run_script(Some(path), &input)
+            }
+            None => 0,
+        };
+        for expr in &exprs {
+            if status != 0 && !keep_going {
+                break;
+            }
+            status = status.max(session.run_script(None, expr));
+        }
+        return Ok(ExitCode::from(status));
+    }
+    let Some(mut editor) = editor else {
+        let mut status = 0;
+        let mut pending = String::new();
+        for line in stdin().lines() {
+            pending.push_str(&line?);
+            if session.is_incomplete(&pending) {
+                pending.push('\n');
                 continue;
             }
+            if let Err(err) = session.run_line(&mem::take(&mut pending)) {
+                session.print_error(&err);
+                status = status.max(exit_status(&err));
+                if !keep_going {
+                    return Ok(ExitCode::from(status));
+                }
+            }
+        }
+        // Reports the unclosed bracket.
+        if !pending.is_empty() {
+            if let Err(err) = session.run_line(&pending) {
+                session.print_error(&err);
+                status = status.max(exit_status(&err));
+            }
+        }
+        return Ok(ExitCode::from(status));
+    };
+    // A missing history file is normal on the first run.
+    if let Some(path) = &history_file {
+        let _ = editor.load_history(path);
+    }
+    // Lines of an input that isn't complete yet.
+    let mut pending = String::new();
+    let result = loop {
+        editor.set_helper(Some(Completion {
+            names: session.names(),
+        }));
+        let prompt = if pending.is_empty() { "> " } else { "... " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C discards the input.
+            Err(ReadlineError::Interrupted) => {
+                pending.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break Ok(ExitCode::SUCCESS),
+            Err(err) => break Err(err.into()),
         };
-        if args.verbose {
-            eprintln!("expr = {:?}", expr);
+        pending.push_str(&line);
+        if session.is_incomplete(&pending) {
+            pending.push('\n');
+            continue;
+        }
+        let input = mem::take(&mut pending);
+        if !input.trim().is_empty() {
+            editor.add_history_entry(&input)?;
+        }
+        if let Err(err) = session.run_line(&input) {
+            session.print_error(&err);
         }
-        match expr.eval(&mut env) {
-            Ok(e) => println!("{}", e),
-            Err(err) => eprintln!("error: {}", err),
+    };
+    if let Some(path) = &history_file {
+        if let Err(err) = editor.save_history(path) {
+            eprintln!(
+                "warning: failed to save history to `{}`: {}",
+                path.display(),
+                err
+            );
         }
     }
+    result
+}
+
+/// An error that was already printed as part of the JSON output, with the
+/// [`exit_status`] of the original error.
+#[derive(Debug)]
+struct Reported(u8);
+
+impl fmt::Display for Reported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "error reported in the output".fmt(f)
+    }
+}
+
+impl std::error::Error for Reported {}
+
+/// A syntax error, rendered against the input.
+#[derive(Debug)]
+struct SyntaxError(String);
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+/// Exit status for a failed input when not at the prompt: 1 for syntax
+/// errors and 2 for any other error.
+fn exit_status(err: &anyhow::Error) -> u8 {
+    match err.downcast_ref() {
+        Some(&Reported(status)) => status,
+        None if err.is::<SyntaxError>() => 1,
+        None => 2,
+    }
+}
+
+/// How often `--watch` checks whether the file changed.
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// SGR parameters of the colored parts of the output.
+const RESULT_COLOR: &str = "32";
+const ERROR_COLOR: &str = "1;31";
+const INPUT_COLOR: &str = "36";
+
+/// Colors text with ANSI escape codes, if enabled for the stream it is
+/// written to.
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    enabled: bool,
+}
+
+impl Style {
+    /// With `--color=auto`, colors a terminal unless `NO_COLOR` is set to
+    /// anything, following https://no-color.org.
+    fn new(choice: ColorChoice, terminal: bool) -> Self {
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        };
+        Self { enabled }
+    }
+    fn paint(self, sgr: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", sgr, text)
+        } else {
+            text.to_string()
+        }
+    }
+    /// Labels `message`, which may hold rendered diagnostics, as an error,
+    /// coloring the quoted input and the carets below it.
+    fn error(self, message: &str) -> String {
+        let label = self.paint(ERROR_COLOR, "error:");
+        let lines: Vec<_> = message
+            .lines()
+            .map(|line| match line.strip_prefix("error:") {
+                Some(rest) => format!("{}{}", label, rest),
+                None if !line.starts_with("  ") => line.to_string(),
+                None if line.trim_start().starts_with('^') => self.paint(ERROR_COLOR, line),
+                None => self.paint(INPUT_COLOR, line),
+            })
+            .collect();
+        format!("{} {}", label, lines.join("\n"))
+    }
+}
+
+/// What `:help` prints before the list of builtin functions.
+const COMMANDS_HELP: &str = "\
+:ast <expr>        show how expr is parsed, as an S-expression
+:clear             clear the screen
+:help <function>   show the documentation of a function
+:info <expr>       show the type, bits and sign of a value, or a function
+:load <file>       replace the variables and functions with a saved session
+:obase [<n>]       print integers in base n, or show the base
+:reset             forget all variables and functions
+:save <file>       save the variables and functions to file
+:time [<expr>]     time evaluating expr, or toggle timing every input
+:type <expr>       show the type of a value
+:whence <expr>     list inputs that evaluated to the value of expr
+!!, !<n>           repeat the previous or the n-th input";
+
+/// REPL commands, written after a `:`.
+const COMMANDS: &[&str] = &[
+    "ast", "clear", "help", "info", "load", "obase", "reset", "save", "time", "type", "whence",
+];
+
+/// Completes commands and the names in [`Session::names`] on Tab.
+struct Completion {
+    names: Vec<String>,
+}
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|c| !is_xid_continue(c))
+            .map_or(0, |i| i + line[i..].chars().next().unwrap().len_utf8());
+        let word = &line[start..pos];
+        let candidates: Vec<_> = if line[..start].trim() == ":" {
+            COMMANDS
+                .iter()
+                .map(|command| format!("{} ", command))
+                .collect()
+        } else if line[..start].trim_start().starts_with(":help") {
+            // Only functions have documentation.
+            (self.names.iter())
+                .filter_map(|name| name.strip_suffix('('))
+                .map(str::to_string)
+                .collect()
+        } else {
+            self.names.clone()
+        };
+        let candidates = candidates.into_iter().filter(|c| c.starts_with(word));
+        Ok((start, candidates.collect()))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+/// Replaces `$NAME` with the value of the process environment variable `NAME`.
+///
+/// Values must be a plain (optionally signed) number; they are substituted
+/// as a parenthesized literal so they cannot change the surrounding syntax.
+fn expand_env_vars(input: &str) -> anyhow::Result<String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(pos) = rest.find('$') {
+        output.push_str(&rest[..pos]);
+        let name_len = rest[pos + 1..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len() - pos - 1);
+        let name = &rest[pos + 1..pos + 1 + name_len];
+        if name.is_empty() {
+            bail!("expected environment variable name after `$`");
+        }
+        let value = env::var(name)
+            .with_context(|| format!("environment variable `{}` is not set", name))?;
+        let value = value.trim();
+        if !is_number(value) {
+            bail!(
+                "environment variable `{}` is not a number: `{}`",
+                name,
+                value
+            );
+        }
+        output.push('(');
+        output.push_str(value);
+        output.push(')');
+        rest = &rest[pos + 1 + name_len..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+fn is_number(s: &str) -> bool {
+    let s = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let (int, frac) = s.split_once('.').unwrap_or((s, "0"));
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    is_digits(int) && is_digits(frac)
 }
@@ -0,0 +1,53 @@
+use crate::eval::EvalResult;
+use num::{traits::Pow, BigInt, One, Signed};
+
+/// Returns `true` if `n` is the square of an integer.
+pub fn is_square(n: &BigInt) -> bool {
+    if n.is_negative() {
+        return false;
+    }
+    let r = n.sqrt();
+    &r * &r == *n
+}
+
+/// Decomposes `n` into `(base, exp)` with `base ** exp == n` and the largest
+/// possible `exp >= 2`, or returns `None` if `n` is not a perfect power.
+///
+/// `0`, `1` and `-1` have no unique decomposition and return `None`.
+///
+/// Only prime exponents are tried: each root found is decomposed further, and
+/// the exponents multiply. `check` is called before every root, so that long
+/// searches can be interrupted.
+pub fn perfect_power(
+    n: &BigInt,
+    check: &dyn Fn() -> EvalResult<()>,
+) -> EvalResult<Option<(BigInt, u32)>> {
+    let mut base = n.abs();
+    if base <= BigInt::one() {
+        return Ok(None);
+    }
+    let mut exp = 1;
+    // Odd powers of a negative base are negative, even ones never are.
+    let mut p = if n.is_negative() { 3 } else { 2 };
+    // `base >= 2`, so it has at least `p + 1` bits if it is a `p`-th power.
+    while u64::from(p) < base.bits() {
+        check()?;
+        let root = base.nth_root(p);
+        if Pow::pow(&root, p) == base {
+            // Smaller primes are not tried again: a root of `root` would be
+            // one of `base` too.
+            base = root;
+            exp *= p;
+            continue;
+        }
+        p = next_prime(p);
+    }
+    Ok((exp > 1).then(|| (if n.is_negative() { -base } else { base }, exp)))
+}
+
+/// The smallest prime above `p`.
+fn next_prime(p: u32) -> u32 {
+    (p + 1..)
+        .find(|&q| (2..).take_while(|d| d * d <= q).all(|d| q % d != 0))
+        .unwrap()
+}
@@ -0,0 +1,130 @@
+use crate::expr::{BinaryOp, Expr, Precedence, UnaryOp};
+use std::fmt::Write;
+
+impl Expr {
+    /// Renders the expression as presentation MathML, wrapped in `<math>`.
+    pub fn to_mathml(&self) -> String {
+        let mut out = String::from(r#"<math xmlns="http://www.w3.org/1998/Math/MathML">"#);
+        write_expr(&mut out, self);
+        out.push_str("</math>");
+        out
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A pending step of [`write_expr`], which works from a stack like the other
+/// walks of [`Expr`]. Pieces are pushed in reverse, as the last one is written
+/// first.
+enum Piece<'a> {
+    Text(&'a str),
+    /// An operator, escaped when written.
+    Op(&'a str),
+    Expr(&'a Expr),
+}
+
+fn push_paren<'a>(stack: &mut Vec<Piece<'a>>, expr: &'a Expr) {
+    stack.extend([
+        Piece::Text("<mo>)</mo></mrow>"),
+        Piece::Expr(expr),
+        Piece::Text("<mrow><mo>(</mo>"),
+    ]);
+}
+
+fn push_operand<'a>(stack: &mut Vec<Piece<'a>>, expr: &'a Expr, paren: bool) {
+    if paren {
+        push_paren(stack, expr);
+    } else {
+        stack.push(Piece::Expr(expr));
+    }
+}
+
+fn write_expr(out: &mut String, expr: &Expr) {
+    let mut stack = vec![Piece::Expr(expr)];
+    while let Some(piece) = stack.pop() {
+        let expr = match piece {
+            Piece::Text(text) => {
+                out.push_str(text);
+                continue;
+            }
+            Piece::Op(symbol) => {
+                let _ = write!(out, "<mo>{}</mo>", escape(symbol));
+                continue;
+            }
+            Piece::Expr(expr) => expr,
+        };
+        match expr {
+            Expr::Int(n) if n.sign() == num::bigint::Sign::Minus => {
+                let _ = write!(out, "<mrow><mo>-</mo><mn>{}</mn></mrow>", n.magnitude());
+            }
+            Expr::Int(n) => {
+                let _ = write!(out, "<mn>{}</mn>", n);
+            }
+            #[cfg(feature = "float")]
+            Expr::Float(lit) => {
+                let _ = write!(out, "<mn>{}</mn>", lit);
+            }
+            Expr::Binary(lhs, BinaryOp::Div, rhs) => {
+                out.push_str("<mfrac>");
+                stack.extend([Piece::Text("</mfrac>"), Piece::Expr(rhs), Piece::Expr(lhs)]);
+            }
+            Expr::Binary(lhs, BinaryOp::Pow, rhs) => {
+                out.push_str("<msup>");
+                stack.extend([Piece::Text("</msup>"), Piece::Expr(rhs)]);
+                push_operand(&mut stack, lhs, lhs.precedence() < Precedence::Primary);
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let precedence = op.precedence();
+                let (l, r) = (lhs.precedence(), rhs.precedence());
+                out.push_str("<mrow>");
+                stack.push(Piece::Text("</mrow>"));
+                push_operand(
+                    &mut stack,
+                    rhs,
+                    r < precedence || r == precedence && !op.is_right(),
+                );
+                stack.push(Piece::Op(match op {
+                    BinaryOp::Mul => "×",
+                    BinaryOp::Rem => "mod",
+                    op => op.symbol(),
+                }));
+                push_operand(
+                    &mut stack,
+                    lhs,
+                    l < precedence || l == precedence && op.is_right(),
+                );
+            }
+            Expr::Unary(op, expr) => {
+                let symbol = match op {
+                    UnaryOp::Plus => "+",
+                    UnaryOp::Minus => "-",
+                };
+                let _ = write!(out, "<mrow><mo>{}</mo>", symbol);
+                stack.push(Piece::Text("</mrow>"));
+                push_operand(&mut stack, expr, expr.precedence() < Precedence::Unary);
+            }
+            Expr::Paren(expr) => push_paren(&mut stack, expr),
+            Expr::Variable(ident) => {
+                let _ = write!(out, "<mi>{}</mi>", escape(ident));
+            }
+            Expr::Call(ident, args) => {
+                let _ = write!(
+                    out,
+                    "<mrow><mi>{}</mi><mo>&#x2061;</mo><mrow><mo>(</mo>",
+                    escape(ident)
+                );
+                stack.push(Piece::Text("<mo>)</mo></mrow></mrow>"));
+                for (i, arg) in args.iter().enumerate().rev() {
+                    stack.push(Piece::Expr(arg));
+                    if i > 0 {
+                        stack.push(Piece::Text("<mo>,</mo>"));
+                    }
+                }
+            }
+        }
+    }
+}
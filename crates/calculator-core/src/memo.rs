@@ -0,0 +1,163 @@
+//! Caching of pure function calls, see [`Environment::set_memoize`].
+
+use crate::{
+    eval::{Environment, Function},
+    expr::{BinaryOp, Expr},
+    value::Value,
+    visit::ExprVisitor,
+};
+use num::BigInt;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Calls whose arguments take up more bytes than this are not cached, so that
+/// keys stay small.
+const MAX_ARGS_SIZE: usize = 1 << 10;
+
+/// Bytes of cached arguments and results beyond which the oldest calls are
+/// forgotten.
+const MAX_SIZE: usize = 16 << 20;
+
+/// Bytes counted for each cached call on top of its arguments and result.
+const ENTRY_SIZE: usize = 64;
+
+/// A function and its evaluated arguments. Unlike [`Value`]'s `PartialEq`,
+/// `2` and `2.0` are different arguments, as the results may differ.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Key {
+    ident: String,
+    args: Vec<Arg>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Arg {
+    Int(BigInt),
+    /// The exact binary representation.
+    #[cfg(feature = "float")]
+    Float(String),
+    Str(String),
+}
+
+impl Key {
+    /// The key of calling `ident` with `args`, or `None` if the arguments are
+    /// too large to cache.
+    pub fn new(ident: &str, args: &[Value]) -> Option<Self> {
+        if args.iter().map(Value::size_hint).sum::<usize>() > MAX_ARGS_SIZE {
+            return None;
+        }
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                Value::Int(n) => Arg::Int(n.clone()),
+                #[cfg(feature = "float")]
+                Value::Float(x) => Arg::Float(format!("{:?}", x)),
+                Value::Str(s) => Arg::Str(s.clone()),
+            })
+            .collect();
+        Some(Self {
+            ident: ident.to_string(),
+            args,
+        })
+    }
+    fn size(&self) -> usize {
+        let args: usize = (self.args.iter())
+            .map(|arg| match arg {
+                Arg::Int(n) => n.bits().div_ceil(8) as usize,
+                #[cfg(feature = "float")]
+                Arg::Float(s) => s.len(),
+                Arg::Str(s) => s.len(),
+            })
+            .sum();
+        ENTRY_SIZE + self.ident.len() + args
+    }
+}
+
+/// Results of calls keyed on the function and its evaluated arguments. The
+/// oldest calls are forgotten once the results take up [`MAX_SIZE`] bytes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Memo {
+    values: HashMap<Key, Value>,
+    /// The keys of `values` from the oldest.
+    order: VecDeque<Key>,
+    size: usize,
+    purity: HashMap<String, bool>,
+}
+
+impl Memo {
+    pub fn get(&self, key: &Key) -> Option<&Value> {
+        self.values.get(key)
+    }
+    pub fn insert(&mut self, key: Key, value: Value) {
+        let size = key.size() + value.size_hint();
+        if size > MAX_SIZE {
+            return;
+        }
+        while self.size + size > MAX_SIZE {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(value) = self.values.remove(&oldest) {
+                self.size -= oldest.size() + value.size_hint();
+            }
+        }
+        if let Some(old) = self.values.insert(key.clone(), value) {
+            self.size -= key.size() + old.size_hint();
+        } else {
+            self.order.push_back(key);
+        }
+        self.size += size;
+    }
+    pub fn purity(&self, ident: &str) -> Option<bool> {
+        self.purity.get(ident).copied()
+    }
+    pub fn set_purity(&mut self, ident: &str, pure: bool) {
+        self.purity.insert(ident.to_string(), pure);
+    }
+}
+
+/// Whether calls of `ident` only depend on their arguments and leave the
+/// environment unchanged. Functions in `visiting` are being checked further up
+/// and are assumed pure, which lets recursive functions qualify.
+pub(crate) fn is_pure(env: &Environment, ident: &str, visiting: &mut HashSet<String>) -> bool {
+    if !visiting.insert(ident.to_string()) {
+        return true;
+    }
+    match env.get_function(ident) {
+        Ok(Function::Native(builtin)) => builtin.pure,
+        Ok(Function::User { params, body, .. }) => {
+            let mut collector = Collector::default();
+            collector.visit_expr(&body);
+            !collector.side_effects
+                && collector
+                    .variables
+                    .iter()
+                    .all(|ident| params.contains(ident) || env.is_constant(ident))
+                && collector
+                    .calls
+                    .iter()
+                    .all(|ident| is_pure(env, ident, visiting))
+        }
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct Collector {
+    side_effects: bool,
+    variables: HashSet<String>,
+    calls: HashSet<String>,
+}
+
+impl ExprVisitor for Collector {
+    fn visit_binary(&mut self, _lhs: &Expr, op: BinaryOp, _rhs: &Expr) {
+        // Custom operators may touch the environment like host functions.
+        if let BinaryOp::Assign | BinaryOp::Custom(_) = op {
+            self.side_effects = true;
+        }
+    }
+    fn visit_variable(&mut self, ident: &str) {
+        self.variables.insert(ident.to_string());
+    }
+    fn visit_call(&mut self, ident: &str, _args: &[Expr]) {
+        self.calls.insert(ident.to_string());
+    }
+}
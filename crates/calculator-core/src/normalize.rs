@@ -0,0 +1,56 @@
+//! Rewriting of the input before it is tokenized.
+
+use crate::token::Span;
+use std::borrow::Cow;
+
+/// Replaces the full-width forms of ASCII characters (`１２３＋４`), as typed
+/// with an IME, and the ideographic space by their ASCII counterparts.
+pub fn full_width(input: &str) -> Cow<'_, str> {
+    Normalized::new(input, true).text
+}
+
+fn to_half_width(c: char) -> Option<char> {
+    match c {
+        '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0),
+        '\u{3000}' => Some(' '),
+        _ => None,
+    }
+}
+
+/// Normalized input that can map spans back to the original.
+pub(crate) struct Normalized<'a> {
+    pub text: Cow<'a, str>,
+    /// Offset in the original input of each byte of `text` and of its end.
+    /// Empty if `text` is unchanged.
+    offsets: Vec<usize>,
+}
+
+impl<'a> Normalized<'a> {
+    pub fn new(input: &'a str, full_width: bool) -> Self {
+        if !full_width || !input.chars().any(|c| to_half_width(c).is_some()) {
+            return Self {
+                text: Cow::Borrowed(input),
+                offsets: Vec::new(),
+            };
+        }
+        let mut text = String::with_capacity(input.len());
+        let mut offsets = Vec::with_capacity(input.len() + 1);
+        for (i, c) in input.char_indices() {
+            let c = to_half_width(c).unwrap_or(c);
+            text.push(c);
+            offsets.resize(text.len(), i);
+        }
+        offsets.push(input.len());
+        Self {
+            text: Cow::Owned(text),
+            offsets,
+        }
+    }
+    /// Maps a span of the normalized text to the original input.
+    pub fn span(&self, span: Span) -> Span {
+        match self.offsets.as_slice() {
+            [] => span,
+            offsets => Span::new(offsets[span.start], offsets[span.end]),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{
+    eval::DEFAULT_PRECISION,
+    expr::{BinaryOp, Expr, UnaryOp},
+    value::Value,
+    visit::ExprFolder,
+};
+use num::ToPrimitive;
+use std::mem;
+
+/// Powers whose result may exceed this many bits are left for evaluation.
+const MAX_FOLD_BITS: u64 = 1 << 16;
+
+impl Expr {
+    /// Pre-evaluates integer subtrees that don't depend on the environment,
+    /// e.g. `2 * 3 + x` becomes `6 + x`.
+    ///
+    /// Subtrees that would fail, such as `1 / 0`, are kept so the error is
+    /// still reported when the expression is evaluated. Float literals are
+    /// kept as well because their value depends on the precision in effect.
+    pub fn fold_constants(self) -> Expr {
+        ConstantFolder.fold_expr(self)
+    }
+}
+
+struct ConstantFolder;
+
+impl ExprFolder for ConstantFolder {
+    fn fold_binary(&mut self, lhs: Expr, op: BinaryOp, rhs: Expr) -> Expr {
+        match (&lhs, op, &rhs) {
+            (_, BinaryOp::Assign, _) => {}
+            (Expr::Int(l), BinaryOp::Pow, Expr::Int(r))
+                if r.to_u64()
+                    .is_none_or(|r| l.bits().saturating_mul(r) > MAX_FOLD_BITS) => {}
+            (Expr::Int(l), op, Expr::Int(r)) => {
+                let result =
+                    Value::Int(l.clone()).binary(op, Value::Int(r.clone()), DEFAULT_PRECISION);
+                if let Ok(Value::Int(n)) = result {
+                    return Expr::Int(n);
+                }
+            }
+            _ => {}
+        }
+        Expr::binary(lhs, op, rhs)
+    }
+    fn fold_unary(&mut self, op: UnaryOp, mut expr: Expr) -> Expr {
+        match (op, &mut expr) {
+            (UnaryOp::Plus, Expr::Int(_)) => expr,
+            (UnaryOp::Minus, Expr::Int(n)) => Expr::Int(-mem::take(n)),
+            _ => Expr::unary(op, expr),
+        }
+    }
+    fn fold_paren(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Int(_) => expr,
+            _ => Expr::Paren(Box::new(expr)),
+        }
+    }
+}
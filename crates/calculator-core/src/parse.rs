@@ -1,33 +1,402 @@
-use crate::token::{tokens, TokenStream};
+use crate::{
+    diagnostic::Diagnostic,
+    expr::{num_lit, BinaryOp, CustomOp, Expr},
+    normalize::Normalized,
+    stmt::Stmt,
+    token::{Lexer, Span, Spanned, Token, TokenStream},
+};
 use num::bigint::ParseBigIntError;
+use std::fmt;
 use thiserror::Error;
 
+/// How deeply brackets, calls and right-associative operators may nest, see
+/// [`TokenStream::nested`].
+pub const MAX_NESTING: usize = 128;
+
 pub trait Parse: Sized {
     fn parse(input: &mut TokenStream) -> ParseResult<Self>;
 }
 
+/// Options of the parser. The free functions use the defaults.
+#[derive(Debug, Clone)]
+pub struct ParserConfig {
+    /// Read full-width characters (`１２３＋４`) as their ASCII forms, see
+    /// [`full_width`](crate::normalize::full_width). Spans still refer to the
+    /// original input.
+    pub full_width: bool,
+    /// Binary operators in addition to the builtin ones.
+    pub operators: Vec<&'static CustomOp>,
+    /// Read `3,14` as a decimal number and separate arguments with `;`
+    /// instead, as is usual where the comma is the decimal separator.
+    pub decimal_comma: bool,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            full_width: true,
+            operators: Vec::new(),
+            decimal_comma: false,
+        }
+    }
+}
+
+impl ParserConfig {
+    pub fn parse_with_spans<T: Parse>(&self, input: &str) -> ParseResult<(T, Vec<Span>)> {
+        let normalized = Normalized::new(input, self.full_width);
+        let lexer = Lexer::with_config(&normalized.text, self);
+        restore_spans(&normalized, parse_tokens(lexer))
+    }
+    /// Parses a `T` at the start of `input`, returning it with the rest of the
+    /// input, which begins after its last token.
+    pub fn parse_prefix<'i, T: Parse>(&self, input: &'i str) -> ParseResult<(T, &'i str)> {
+        let normalized = Normalized::new(input, self.full_width);
+        let mut stream = TokenStream::from_lexer(Lexer::with_config(&normalized.text, self));
+        let t = stream.parse().map_err(|err| {
+            let err = stream.locate(err);
+            ParseError {
+                span: err.span.map(|span| normalized.span(span)),
+                ..err
+            }
+        })?;
+        let offset = stream.offset();
+        let end = normalized.span(Span::new(offset, offset)).end;
+        Ok((t, &input[end..]))
+    }
+    pub fn parse_rpn_with_spans(&self, input: &str) -> ParseResult<(Expr, Vec<Span>)> {
+        let normalized = Normalized::new(input, self.full_width);
+        let lexer = Lexer::with_config(&normalized.text, self);
+        restore_spans(&normalized, parse_rpn_tokens(lexer))
+    }
+    pub fn parse_script(&self, input: &str) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+        let normalized = Normalized::new(input, self.full_width);
+        parse_script_normalized(&normalized.text, self).map_err(|diagnostics| {
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| Diagnostic {
+                    span: diagnostic.span.map(|span| normalized.span(span)),
+                    ..diagnostic
+                })
+                .collect()
+        })
+    }
+}
+
+/// Maps the spans of a result for normalized input back to the original.
+fn restore_spans<T>(
+    normalized: &Normalized,
+    result: ParseResult<(T, Vec<Span>)>,
+) -> ParseResult<(T, Vec<Span>)> {
+    match result {
+        Ok((t, spans)) => Ok((
+            t,
+            spans
+                .into_iter()
+                .map(|span| normalized.span(span))
+                .collect(),
+        )),
+        Err(err) => Err(ParseError {
+            span: err.span.map(|span| normalized.span(span)),
+            ..err
+        }),
+    }
+}
+
 pub fn parse_from_str<T: Parse>(input: &str) -> ParseResult<T> {
-    let tokens = tokens(input)?;
-    let mut stream = TokenStream::new(&tokens);
-    let t = stream.parse()?;
-    stream.eof()?;
-    Ok(t)
+    parse_with_spans(input).map(|(t, _)| t)
+}
+
+/// Like [`parse_from_str`], also returning the spans of the expression nodes in
+/// post-order, the order they are built in. [`EvalError::span`] looks up the
+/// span of a failed node there.
+///
+/// [`EvalError::span`]: crate::eval::EvalError::span
+pub fn parse_with_spans<T: Parse>(input: &str) -> ParseResult<(T, Vec<Span>)> {
+    ParserConfig::default().parse_with_spans(input)
+}
+
+fn parse_tokens<T: Parse>(lexer: Lexer) -> ParseResult<(T, Vec<Span>)> {
+    let mut stream = TokenStream::from_lexer(lexer);
+    let t = stream
+        .parse()
+        .and_then(|t| stream.eof().map(|_| t))
+        .map_err(|err| stream.locate(err))?;
+    Ok((t, stream.into_spans()))
+}
+
+/// Parses a `T` at the start of `input` and returns the rest, e.g. `1 + 2; 3`
+/// gives `1 + 2` and `"; 3"`. Unlike [`parse_from_str`], the input doesn't
+/// have to end after it, so that callers can use their own separators.
+pub fn parse_prefix<T: Parse>(input: &str) -> ParseResult<(T, &str)> {
+    ParserConfig::default().parse_prefix(input)
+}
+
+/// Parses each line of `input` as a [`Stmt`], skipping blank and comment-only
+/// lines, and reports every syntax error rather than only the first.
+///
+/// After an error, the rest of the line is parsed again from the token after
+/// the offending one. Lines with unexpected characters are not parsed, as most
+/// errors there would follow from the missing characters.
+pub fn parse_script(input: &str) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+    ParserConfig::default().parse_script(input)
+}
+
+/// The inputs of a script to evaluate one by one: its nonblank lines, each
+/// joined with those after it while it ends inside brackets.
+pub fn script_inputs(input: &str) -> Vec<String> {
+    let mut inputs: Vec<String> = Vec::new();
+    let mut continued = false;
+    for line in input.lines() {
+        match inputs.last_mut() {
+            Some(input) if continued => {
+                input.push('\n');
+                input.push_str(line);
+            }
+            _ if line.trim().is_empty() => continue,
+            _ => inputs.push(line.to_string()),
+        }
+        continued = is_incomplete(inputs.last().unwrap());
+    }
+    inputs
+}
+
+/// Whether `input` ends inside brackets, so that the next line continues it.
+/// Lines starting with `:` are commands of the frontends and never continue.
+pub fn is_incomplete(input: &str) -> bool {
+    !input.trim_start().starts_with(':')
+        && matches!(parse_with_spans::<Stmt>(input), Err(err) if err.is_incomplete())
+}
+
+fn parse_script_normalized(
+    input: &str,
+    config: &ParserConfig,
+) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+    let mut stmts = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        let (tokens, mut errors) = Lexer::with_config(line, config).collect_recovering();
+        if errors.is_empty()
+            && tokens
+                .iter()
+                .any(|token| !matches!(token.value, Token::Doc(_)))
+        {
+            let mut rest = &tokens[..];
+            loop {
+                let mut stream = TokenStream::new(rest);
+                let result = stream
+                    .parse::<Stmt>()
+                    .and_then(|stmt| stream.eof().map(|_| stmt))
+                    .map_err(|err| stream.locate(err));
+                match result {
+                    Ok(stmt) if rest.len() == tokens.len() => stmts.push(stmt),
+                    Ok(_) => {}
+                    Err(err) if err.is_incomplete() => errors.push(err),
+                    Err(err) => {
+                        let end = err.span.map_or(0, |span| span.end);
+                        errors.push(err);
+                        let skip = rest.iter().take_while(|token| token.span.start < end);
+                        let mut skip = skip.count().max(1).min(rest.len());
+                        // The rest of the brackets around the error would not
+                        // parse on their own, e.g. `2)` in `f(1 2)`.
+                        let mut depth = rest[..skip].iter().fold(0, nesting);
+                        while depth > 0 && skip < rest.len() {
+                            depth = nesting(depth, &rest[skip]);
+                            skip += 1;
+                        }
+                        rest = &rest[skip..];
+                        if !rest.is_empty() {
+                            continue;
+                        }
+                    }
+                }
+                break;
+            }
+        }
+        diagnostics.extend(errors.into_iter().map(|err| {
+            let span = err
+                .span
+                .map(|span| Span::new(span.start + offset, span.end + offset));
+            Diagnostic::new(err, span)
+        }));
+        offset += line.len();
+    }
+    if diagnostics.is_empty() {
+        Ok(stmts)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Bracket nesting depth after `token`.
+fn nesting(depth: usize, token: &Spanned<Token>) -> usize {
+    match (token.value.bracket(), token.value.closer()) {
+        (Some(_), Some(_)) => depth + 1,
+        (Some(_), None) => depth.saturating_sub(1),
+        _ => depth,
+    }
+}
+
+/// Parses postfix (reverse Polish) input such as `3 4 + 5 *` into the same
+/// tree as the infix `(3 + 4) * 5`. Assignment is written `x 3 =` and a call
+/// taking `n` arguments from the stack is written `f(n)`.
+pub fn parse_rpn(input: &str) -> ParseResult<Expr> {
+    parse_rpn_with_spans(input).map(|(expr, _)| expr)
+}
+
+/// Like [`parse_rpn`], also returning the node spans as [`parse_with_spans`]
+/// does.
+pub fn parse_rpn_with_spans(input: &str) -> ParseResult<(Expr, Vec<Span>)> {
+    ParserConfig::default().parse_rpn_with_spans(input)
+}
+
+fn parse_rpn_tokens(lexer: Lexer) -> ParseResult<(Expr, Vec<Span>)> {
+    let mut input = TokenStream::from_lexer(lexer);
+    // Operands with the start of their input.
+    let mut stack: Vec<(Expr, usize)> = Vec::new();
+    let mut spans = Vec::new();
+    while input.eof().is_err() {
+        let span = input.span();
+        let missing = || ParseError::at(ParseErrorKind::MissingOperand, span);
+        let (expr, start) = match input.consume()? {
+            Token::NumLit(lit) => (num_lit(lit).map_err(|err| err.with_span(span))?, span.start),
+            Token::VarLit(ident) => {
+                let ident = ident.to_string();
+                if matches!(input.peek(), Ok(Token::LParen)) {
+                    input.consume()?;
+                    let n: usize = match input.peek()? {
+                        Token::NumLit(lit) => lit.parse().map_err(|_| ParseErrorKind::ExpectedNum),
+                        _ => Err(ParseErrorKind::ExpectedNum),
+                    }
+                    .map_err(|kind| input.error(kind))?;
+                    input.consume()?;
+                    match input.peek()? {
+                        Token::RParen => input.consume()?,
+                        _ => Err(input.error(ParseErrorKind::ExpectedClosing(')')))?,
+                    };
+                    let at = stack.len().checked_sub(n).ok_or_else(missing)?;
+                    let start = stack.get(at).map_or(span.start, |(_, start)| *start);
+                    let args = stack.split_off(at).into_iter().map(|(arg, _)| arg);
+                    (Expr::Call(ident, args.collect()), start)
+                } else {
+                    (Expr::Variable(ident), span.start)
+                }
+            }
+            Token::Doc(_) => continue,
+            token => {
+                let tokens = [Spanned::new(token, span)];
+                let stream = TokenStream::new(&tokens);
+                let op = BinaryOp::peek(&stream).map_err(|err| stream.locate(err))?;
+                let (rhs, _) = stack.pop().ok_or_else(missing)?;
+                let (lhs, start) = stack.pop().ok_or_else(missing)?;
+                (Expr::binary(lhs, op, rhs), start)
+            }
+        };
+        spans.push(Span::new(start, span.end));
+        stack.push((expr, start));
+    }
+    let (expr, _) = stack
+        .pop()
+        .ok_or(input.error(ParseErrorKind::UnexpectedEndOfInput))?;
+    match stack.last() {
+        None => Ok((expr, spans)),
+        Some((_, start)) => Err(ParseError::at(
+            ParseErrorKind::ExtraOperand,
+            Span::new(*start, input.span().end),
+        )),
+    }
+}
+
+/// A syntax error and where it was found.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// The offending part of the input, if known.
+    pub span: Option<Span>,
+    /// Text of the offending token, if known.
+    pub found: Option<String>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.kind.fmt(f)?;
+        match &self.found {
+            Some(found) => write!(f, ", found `{}`", found),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    pub fn at(kind: impl Into<ParseErrorKind>, span: Span) -> Self {
+        Self {
+            kind: kind.into(),
+            span: Some(span),
+            found: None,
+        }
+    }
+    /// Sets the span unless it is already known.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+    /// Whether the input ended inside brackets, so that appending more
+    /// input, such as another line, may complete it.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self.kind, ParseErrorKind::Unclosed(_))
+    }
+}
+
+impl From<ParseErrorKind> for ParseError {
+    fn from(kind: ParseErrorKind) -> Self {
+        Self {
+            kind,
+            span: None,
+            found: None,
+        }
+    }
+}
+
+impl From<ParseBigIntError> for ParseError {
+    fn from(err: ParseBigIntError) -> Self {
+        ParseErrorKind::from(err).into()
+    }
 }
 
 #[derive(Debug, Clone, Error)]
-pub enum ParseError {
+pub enum ParseErrorKind {
     #[error("expected one of `+-`")]
     ExpectedUnary,
     #[error("expected one of `+-*/%`")]
     ExpectedBinary,
+    #[error("expected an expression")]
+    ExpectedExpr,
     #[error("expected digits")]
     ExpectedNum,
-    #[error("expected `)`")]
-    ExpectedRParen,
+    #[error("expected `{0}`")]
+    ExpectedClosing(char),
     #[error("unexpected end of input")]
     UnexpectedEndOfInput,
-    #[error("unexpected token")]
-    UnexpectedToken,
+    #[error("unclosed `{0}`")]
+    Unclosed(char),
+    #[error("argument {position} of `{ident}` is empty")]
+    EmptyArgument { ident: String, position: usize },
+    #[error("expected `,` or `)` after argument {position} of `{ident}`")]
+    ExpectedArgumentEnd { ident: String, position: usize },
+    #[error("`{0}` is a reserved word")]
+    Reserved(&'static str),
+    #[error("expected end of input")]
+    ExpectedEnd,
+    #[error("unexpected character `{0}`")]
+    UnexpectedChar(char),
+    #[error("missing operand")]
+    MissingOperand,
+    #[error("too many operands")]
+    ExtraOperand,
+    #[error("expression is nested more than {MAX_NESTING} levels deep")]
+    TooDeep,
     #[error("unexpected integer literal")]
     ParseBigIntError(#[from] ParseBigIntError),
 }
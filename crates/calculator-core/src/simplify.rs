@@ -0,0 +1,68 @@
+use crate::{
+    expr::{BinaryOp, Expr, UnaryOp},
+    visit::{walk_expr, ExprFolder, ExprVisitor},
+};
+use num::{One, Zero};
+use std::mem;
+
+impl Expr {
+    /// Applies algebraic identities such as `x + 0`, `x * 1`, `x * 0`, `x - x`
+    /// and `--x`, and drops redundant parentheses.
+    ///
+    /// Operands containing assignments are never dropped. Dropping other
+    /// operands may hide errors they would raise, e.g. `(1 / 0) * 0` becomes `0`.
+    pub fn simplify(self) -> Expr {
+        Simplifier.fold_expr(self)
+    }
+}
+
+struct Simplifier;
+
+fn is_int(expr: &Expr, pred: impl Fn(&num::BigInt) -> bool) -> bool {
+    matches!(expr, Expr::Int(n) if pred(n))
+}
+
+fn is_pure(expr: &Expr) -> bool {
+    struct Assigns(bool);
+    impl ExprVisitor for Assigns {
+        fn visit_binary(&mut self, _lhs: &Expr, op: BinaryOp, _rhs: &Expr) {
+            self.0 |= op == BinaryOp::Assign;
+        }
+    }
+    let mut assigns = Assigns(false);
+    walk_expr(&mut assigns, expr);
+    !assigns.0
+}
+
+impl ExprFolder for Simplifier {
+    fn fold_binary(&mut self, lhs: Expr, op: BinaryOp, rhs: Expr) -> Expr {
+        use BinaryOp::*;
+        match op {
+            Add if is_int(&rhs, Zero::is_zero) => lhs,
+            Add if is_int(&lhs, Zero::is_zero) => rhs,
+            Sub if is_int(&rhs, Zero::is_zero) => lhs,
+            Sub if is_int(&lhs, Zero::is_zero) => self.fold_unary(UnaryOp::Minus, rhs),
+            Sub if lhs == rhs && is_pure(&lhs) => Expr::int(0),
+            Mul if is_int(&rhs, One::is_one) => lhs,
+            Mul if is_int(&lhs, One::is_one) => rhs,
+            Mul if is_int(&rhs, Zero::is_zero) && is_pure(&lhs) => rhs,
+            Mul if is_int(&lhs, Zero::is_zero) && is_pure(&rhs) => lhs,
+            Div if is_int(&rhs, One::is_one) => lhs,
+            Pow if is_int(&rhs, One::is_one) => lhs,
+            Pow if is_int(&rhs, Zero::is_zero) && is_pure(&lhs) => Expr::int(1),
+            _ => Expr::binary(lhs, op, rhs),
+        }
+    }
+    fn fold_unary(&mut self, op: UnaryOp, mut expr: Expr) -> Expr {
+        match (op, &mut expr) {
+            (UnaryOp::Plus, _) => expr,
+            (UnaryOp::Minus, Expr::Unary(UnaryOp::Minus, inner)) => mem::take(&mut **inner),
+            (UnaryOp::Minus, Expr::Int(n)) if n.is_zero() => expr,
+            _ => Expr::unary(op, expr),
+        }
+    }
+    fn fold_paren(&mut self, expr: Expr) -> Expr {
+        // `Display` adds back the parentheses the precedence requires.
+        expr
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{
+    eval::{Environment, Eval, EvalResult, Function},
+    expr::{BinaryOp, Expr},
+    parse::{Parse, ParseResult},
+    token::{Token, TokenStream},
+    value::Value,
+};
+use std::{mem, sync::Arc};
+
+/// A single line of input.
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Expr(Expr),
+    /// `f(x, y) = body ## doc`
+    Define {
+        ident: String,
+        params: Vec<String>,
+        body: Expr,
+        doc: Option<String>,
+    },
+}
+
+impl Eval for Stmt {
+    /// `None` for statements without a value, i.e. function definitions.
+    type Output = Option<Value>;
+
+    fn eval(self, env: &mut Environment) -> EvalResult<Self::Output> {
+        Ok(match self {
+            Stmt::Expr(expr) => Some(expr.eval(env)?),
+            Stmt::Define {
+                ident,
+                params,
+                body,
+                doc,
+            } => {
+                let body = Arc::new(body.fold_constants());
+                env.set_function(ident, Function::User { params, body, doc });
+                None
+            }
+        })
+    }
+}
+
+impl Parse for Stmt {
+    fn parse(input: &mut TokenStream) -> ParseResult<Self> {
+        let mut expr: Expr = input.parse()?;
+        // A doc comment is only kept for definitions, elsewhere it is a plain comment.
+        let doc = match input.peek() {
+            Ok(Token::Doc(doc)) => {
+                let doc = doc.to_string();
+                input.consume()?;
+                Some(doc)
+            }
+            _ => None,
+        };
+        // `Expr` implements `Drop`, so the parts of a definition are taken out
+        // rather than moved by destructuring.
+        if let Expr::Binary(lhs, BinaryOp::Assign, body) = &mut expr {
+            if let Expr::Call(ident, args) = &mut **lhs {
+                if args.iter().all(|arg| matches!(arg, Expr::Variable(_))) {
+                    let params = args
+                        .iter_mut()
+                        .map(|arg| match arg {
+                            Expr::Variable(param) => mem::take(param),
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    return Ok(Stmt::Define {
+                        ident: mem::take(ident),
+                        params,
+                        body: mem::take(&mut **body),
+                        doc,
+                    });
+                }
+            }
+        }
+        Ok(Stmt::Expr(expr))
+    }
+}
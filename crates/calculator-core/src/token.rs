@@ -1,6 +1,43 @@
-use crate::parse::{Parse, ParseError, ParseResult};
+use crate::{
+    expr::CustomOp,
+    parse::{Parse, ParseError, ParseErrorKind, ParseResult, ParserConfig, MAX_NESTING},
+};
+use std::{fmt, ops::Range, slice};
 use unicode_ident::{is_xid_continue, is_xid_start};
 
+/// Byte range in the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+    pub fn range(self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// A value with the span of the input it was read from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(value: T, span: Span) -> Self {
+        Self { value, span }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Token<'s> {
     NumLit(&'s str),
@@ -13,26 +50,228 @@ pub enum Token<'s> {
     Percent,
     LParen,
     RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
     Comma,
     Equal,
+    /// `## text` up to the end of the input, with surrounding whitespace trimmed.
+    Doc(&'s str),
+    /// A run of superscript digits and signs such as `²` or `⁻¹`.
+    Superscript(&'s str),
+    Custom(&'static CustomOp),
+    /// A reserved word, which can't be used as a name.
+    Keyword(Keyword),
+}
+
+/// Words reserved for syntax, see [`KEYWORDS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyword {
+    Let,
+    If,
+    Else,
+    Fn,
+    True,
+    False,
+}
+
+/// Every keyword with its text.
+pub const KEYWORDS: &[(&str, Keyword)] = &[
+    ("let", Keyword::Let),
+    ("if", Keyword::If),
+    ("else", Keyword::Else),
+    ("fn", Keyword::Fn),
+    ("true", Keyword::True),
+    ("false", Keyword::False),
+];
+
+impl Keyword {
+    pub fn from_ident(ident: &str) -> Option<Self> {
+        KEYWORDS
+            .iter()
+            .find(|(text, _)| *text == ident)
+            .map(|(_, keyword)| *keyword)
+    }
+    pub fn as_str(&self) -> &'static str {
+        KEYWORDS
+            .iter()
+            .find(|(_, keyword)| keyword == self)
+            .map(|(text, _)| *text)
+            .unwrap()
+    }
+}
+
+impl Token<'_> {
+    /// The character of a bracket token, one of `()[]{}`.
+    pub fn bracket(&self) -> Option<char> {
+        Some(match self {
+            Token::LParen => '(',
+            Token::RParen => ')',
+            Token::LBracket => '[',
+            Token::RBracket => ']',
+            Token::LBrace => '{',
+            Token::RBrace => '}',
+            _ => None?,
+        })
+    }
+    /// The bracket closing a group opened by this token.
+    pub fn closer(&self) -> Option<char> {
+        Some(match self {
+            Token::LParen => ')',
+            Token::LBracket => ']',
+            Token::LBrace => '}',
+            _ => None?,
+        })
+    }
 }
 
-pub fn tokens(mut s: &str) -> ParseResult<Vec<Token>> {
-    macro_rules! symbol_arm {
-        ($token:expr) => {{
-            let (_, s1) = s.split_at(1);
-            s = s1;
-            $token
-        }};
+/// The text of the token.
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::NumLit(s) | Token::VarLit(s) | Token::Superscript(s) => s.fmt(f),
+            Token::Plus => "+".fmt(f),
+            Token::Minus => "-".fmt(f),
+            Token::Ast => "*".fmt(f),
+            Token::AstAst => "**".fmt(f),
+            Token::Slash => "/".fmt(f),
+            Token::Percent => "%".fmt(f),
+            Token::LParen => "(".fmt(f),
+            Token::RParen => ")".fmt(f),
+            Token::LBracket => "[".fmt(f),
+            Token::RBracket => "]".fmt(f),
+            Token::LBrace => "{".fmt(f),
+            Token::RBrace => "}".fmt(f),
+            Token::Comma => ",".fmt(f),
+            Token::Equal => "=".fmt(f),
+            Token::Doc(doc) => write!(f, "## {}", doc),
+            Token::Custom(op) => op.symbol.fmt(f),
+            Token::Keyword(keyword) => keyword.as_str().fmt(f),
+        }
     }
-    let mut buffer = Vec::new();
-    while !s.is_empty() {
+}
+
+/// The ASCII digit or sign a superscript character stands for.
+pub fn from_superscript(c: char) -> Option<char> {
+    Some(match c {
+        '⁰' => '0',
+        '¹' => '1',
+        '²' => '2',
+        '³' => '3',
+        '⁴'..='⁹' => char::from_u32(c as u32 - '⁴' as u32 + '4' as u32)?,
+        '⁺' => '+',
+        '⁻' => '-',
+        _ => None?,
+    })
+}
+
+pub fn tokens(input: &str) -> ParseResult<Vec<Spanned<Token<'_>>>> {
+    Lexer::new(input).collect()
+}
+
+/// Like [`tokens`], skipping unexpected characters and returning an error for
+/// each of them.
+pub fn tokens_recovering(input: &str) -> (Vec<Spanned<Token<'_>>>, Vec<ParseError>) {
+    Lexer::new(input).collect_recovering()
+}
+
+/// Reads tokens on demand. An unexpected character yields an error and is
+/// skipped.
+#[derive(Debug, Clone)]
+pub struct Lexer<'s> {
+    input: &'s str,
+    rest: &'s str,
+    operators: &'s [&'static CustomOp],
+    decimal_comma: bool,
+}
+
+impl<'s> Lexer<'s> {
+    pub fn new(input: &'s str) -> Self {
+        Self {
+            input,
+            rest: input,
+            operators: &[],
+            decimal_comma: false,
+        }
+    }
+    /// Reads `input` with the custom operators and number syntax of `config`.
+    pub fn with_config(input: &'s str, config: &'s ParserConfig) -> Self {
+        Self {
+            operators: &config.operators,
+            decimal_comma: config.decimal_comma,
+            ..Self::new(input)
+        }
+    }
+    /// Reads all tokens, returning the errors separately.
+    pub fn collect_recovering(self) -> (Vec<Spanned<Token<'s>>>, Vec<ParseError>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for token in self {
+            match token {
+                Ok(token) => tokens.push(token),
+                Err(err) => errors.push(err),
+            }
+        }
+        (tokens, errors)
+    }
+    /// The longest custom operator at the start of `s`.
+    fn custom_op(&self, s: &str) -> Option<&'static CustomOp> {
+        let ends_word = |symbol: &str| {
+            !(symbol.ends_with(is_xid_continue) && s[symbol.len()..].starts_with(is_xid_continue))
+        };
+        self.operators
+            .iter()
+            .copied()
+            .filter(|op| !op.symbol.is_empty() && s.starts_with(op.symbol) && ends_word(op.symbol))
+            .max_by_key(|op| op.symbol.len())
+    }
+}
+
+impl<'s> Iterator for Lexer<'s> {
+    type Item = ParseResult<Spanned<Token<'s>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let input = self.input;
+        let mut s = self
+            .rest
+            .trim_start_matches(|c: char| c.is_ascii_whitespace());
+        if s.is_empty() {
+            self.rest = s;
+            return None;
+        }
+        macro_rules! symbol_arm {
+            ($token:expr) => {{
+                let (_, s1) = s.split_at(1);
+                s = s1;
+                $token
+            }};
+        }
+        let start = input.len() - s.len();
+        if let Some(op) = self.custom_op(s) {
+            self.rest = &s[op.symbol.len()..];
+            let span = Span::new(start, start + op.symbol.len());
+            return Some(Ok(Spanned::new(Token::Custom(op), span)));
+        }
         let token = match s.chars().next().unwrap() {
             '0'..='9' => {
                 let pos = s
                     .chars()
                     .position(|c| !c.is_ascii_digit())
                     .unwrap_or(s.len());
+                #[cfg(feature = "float")]
+                let point = if self.decimal_comma { ',' } else { '.' };
+                #[cfg(feature = "float")]
+                let pos = match s[pos..].strip_prefix(point) {
+                    Some(frac) => {
+                        pos + 1
+                            + frac
+                                .chars()
+                                .position(|c| !c.is_ascii_digit())
+                                .unwrap_or(frac.len())
+                    }
+                    None => pos,
+                };
                 let (lit, spos) = s.split_at(pos);
                 s = spos;
                 Token::NumLit(lit)
@@ -52,12 +291,23 @@ pub fn tokens(mut s: &str) -> ParseResult<Vec<Token>> {
             '%' => symbol_arm!(Token::Percent),
             '(' => symbol_arm!(Token::LParen),
             ')' => symbol_arm!(Token::RParen),
-            ',' => symbol_arm!(Token::Comma),
+            '[' => symbol_arm!(Token::LBracket),
+            ']' => symbol_arm!(Token::RBracket),
+            '{' => symbol_arm!(Token::LBrace),
+            '}' => symbol_arm!(Token::RBrace),
+            ',' if !self.decimal_comma => symbol_arm!(Token::Comma),
+            ';' if self.decimal_comma => symbol_arm!(Token::Comma),
             '=' => symbol_arm!(Token::Equal),
-            c if c.is_ascii_whitespace() => {
-                let (_, s1) = s.split_at(1);
-                s = s1;
-                continue;
+            '#' if s.starts_with("##") => {
+                let doc = s[2..].trim();
+                s = "";
+                Token::Doc(doc)
+            }
+            c if from_superscript(c).is_some() => {
+                let pos = s.find(|c| from_superscript(c).is_none()).unwrap_or(s.len());
+                let (lit, spos) = s.split_at(pos);
+                s = spos;
+                Token::Superscript(lit)
             }
             c if is_xid_start(c) => {
                 let slen = c.len_utf8();
@@ -69,44 +319,216 @@ pub fn tokens(mut s: &str) -> ParseResult<Vec<Token>> {
                     + slen;
                 let (lit, spos) = s.split_at(pos);
                 s = spos;
-                Token::VarLit(lit)
+                match Keyword::from_ident(lit) {
+                    Some(keyword) => Token::Keyword(keyword),
+                    None => Token::VarLit(lit),
+                }
+            }
+            c => {
+                s = &s[c.len_utf8()..];
+                self.rest = s;
+                let span = Span::new(start, start + c.len_utf8());
+                return Some(Err(ParseError::at(ParseErrorKind::UnexpectedChar(c), span)));
             }
-            _ => Err(ParseError::UnexpectedToken)?,
         };
-        buffer.push(token);
+        self.rest = s;
+        let span = Span::new(start, input.len() - s.len());
+        Some(Ok(Spanned::new(token, span)))
     }
-    Ok(buffer)
 }
 
+/// Where a [`TokenStream`] reads its tokens from.
+#[derive(Debug, Clone)]
+enum Source<'a> {
+    Lexer(Lexer<'a>),
+    Tokens(slice::Iter<'a, Spanned<Token<'a>>>),
+}
+
+impl<'a> Iterator for Source<'a> {
+    type Item = ParseResult<Spanned<Token<'a>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Source::Lexer(lexer) => lexer.next(),
+            Source::Tokens(tokens) => tokens.next().copied().map(Ok),
+        }
+    }
+}
+
+/// Tokens with one token of lookahead, or more with
+/// [`TokenStream::peek_nth`].
 #[derive(Debug)]
 pub struct TokenStream<'a> {
-    tokens: &'a [Token<'a>],
+    source: Source<'a>,
+    /// The token [`TokenStream::peek`] returns, `None` at the end of input.
+    next: Option<ParseResult<Spanned<Token<'a>>>>,
+    /// End of the last consumed token.
+    consumed: usize,
+    /// Spans of the parsed expression nodes in post-order.
+    spans: Vec<Span>,
+    /// The consumed opening brackets that are not closed yet.
+    open: Vec<Spanned<char>>,
+    /// Nesting level of [`TokenStream::nested`].
+    depth: usize,
 }
 
 impl<'a> TokenStream<'a> {
-    pub fn new(tokens: &'a [Token<'a>]) -> Self {
-        Self { tokens }
+    pub fn new(tokens: &'a [Spanned<Token<'a>>]) -> Self {
+        Self::from_source(Source::Tokens(tokens.iter()))
+    }
+    /// Tokenizes `input` while it is parsed.
+    pub fn from_lexer(lexer: Lexer<'a>) -> Self {
+        Self::from_source(Source::Lexer(lexer))
+    }
+    fn from_source(mut source: Source<'a>) -> Self {
+        let next = source.next();
+        Self {
+            source,
+            next,
+            consumed: 0,
+            spans: Vec::new(),
+            open: Vec::new(),
+            depth: 0,
+        }
     }
     pub fn parse<T: Parse>(&mut self) -> ParseResult<T> {
         <T as Parse>::parse(self)
     }
-    pub fn peek(&self) -> ParseResult<&Token> {
-        self.tokens.first().ok_or(ParseError::UnexpectedEndOfInput)
+    pub fn peek(&self) -> ParseResult<Token<'a>> {
+        match &self.next {
+            Some(Ok(token)) => Ok(token.value),
+            Some(Err(err)) => Err(err.clone()),
+            None => Err(self.error(ParseErrorKind::UnexpectedEndOfInput)),
+        }
     }
-    pub fn consume(&mut self) -> ParseResult<Token> {
-        if !self.tokens.is_empty() {
-            let (first, res) = self.tokens.split_at(1);
-            self.tokens = res;
-            Ok(unsafe { *first.get_unchecked(0) })
-        } else {
-            Err(ParseError::UnexpectedEndOfInput)
+    /// The token `n` tokens after the next one, so `peek_nth(0)` is
+    /// [`TokenStream::peek`]. Tokens after the next one are read again on
+    /// every call.
+    pub fn peek_nth(&self, n: usize) -> ParseResult<Token<'a>> {
+        let token = self.peek()?;
+        if n == 0 {
+            return Ok(token);
+        }
+        let mut source = self.source.clone();
+        let mut end = self.span().end;
+        for _ in 1..n {
+            match source.next() {
+                Some(token) => end = token?.span.end,
+                None => break,
+            }
+        }
+        match source.next() {
+            Some(token) => Ok(token?.value),
+            None => Err(ParseError::at(
+                ParseErrorKind::UnexpectedEndOfInput,
+                Span::new(end, end),
+            )),
         }
     }
+    /// Span of the next token, or an empty span at the end of the input.
+    pub fn span(&self) -> Span {
+        match &self.next {
+            Some(Ok(token)) => token.span,
+            Some(Err(ParseError {
+                span: Some(span), ..
+            })) => *span,
+            _ => Span::new(self.consumed, self.consumed),
+        }
+    }
+    pub fn consume(&mut self) -> ParseResult<Token<'a>> {
+        let token = self.peek()?;
+        match (token.bracket(), token.closer()) {
+            (Some(bracket), Some(_)) => self.open.push(Spanned::new(bracket, self.span())),
+            (Some(_), None) => {
+                self.open.pop();
+            }
+            _ => {}
+        }
+        self.consumed = self.span().end;
+        self.next = self.source.next();
+        Ok(token)
+    }
+    /// Byte offset after the last consumed token.
+    pub fn offset(&self) -> usize {
+        self.consumed
+    }
     pub fn eof(&self) -> ParseResult<()> {
-        if self.tokens.is_empty() {
-            Ok(())
-        } else {
-            Err(ParseError::UnexpectedToken)
+        match &self.next {
+            None => Ok(()),
+            Some(Err(err)) => Err(err.clone()),
+            Some(Ok(_)) => Err(self.error(ParseErrorKind::ExpectedEnd)),
+        }
+    }
+    /// An error at the next token, which is noted as found instead. At the end
+    /// of input inside brackets, the error is [`ParseErrorKind::Unclosed`] at
+    /// the innermost opening bracket instead.
+    pub fn error(&self, kind: ParseErrorKind) -> ParseError {
+        match (&self.next, self.open.last()) {
+            (None, Some(open)) => ParseError::at(ParseErrorKind::Unclosed(open.value), open.span),
+            (Some(Ok(token)), _) => ParseError {
+                found: Some(token.value.to_string()),
+                ..ParseError::at(kind, token.span)
+            },
+            _ => ParseError::at(kind, self.span()),
         }
     }
+    /// Places an error that has no span yet at the next token, as
+    /// [`TokenStream::error`] does.
+    pub fn locate(&self, err: ParseError) -> ParseError {
+        match err.span {
+            Some(_) => err,
+            None => self.error(err.kind),
+        }
+    }
+    /// Records the span of an expression node from `start` to the end of the
+    /// last consumed token. Nodes are recorded in post-order, as they are built.
+    pub fn mark(&mut self, start: usize) {
+        self.spans.push(Span::new(start, self.consumed));
+    }
+    /// Runs `f` one nesting level deeper, failing with
+    /// [`ParseErrorKind::TooDeep`] past [`MAX_NESTING`] levels so that the
+    /// recursive descent can't overflow the call stack.
+    pub fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        if self.depth >= MAX_NESTING {
+            return Err(ParseError::at(ParseErrorKind::TooDeep, self.span()));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+    /// The current position, to return to with [`TokenStream::rewind`].
+    pub fn checkpoint(&self) -> Checkpoint<'a> {
+        Checkpoint {
+            source: self.source.clone(),
+            next: self.next.clone(),
+            consumed: self.consumed,
+            spans: self.spans.len(),
+            open: self.open.clone(),
+        }
+    }
+    /// Goes back to `checkpoint`, as if the tokens consumed since were not.
+    /// Spans marked since are dropped too.
+    pub fn rewind(&mut self, checkpoint: Checkpoint<'a>) {
+        self.source = checkpoint.source;
+        self.next = checkpoint.next;
+        self.consumed = checkpoint.consumed;
+        self.spans.truncate(checkpoint.spans);
+        self.open = checkpoint.open;
+    }
+    /// The spans recorded with [`TokenStream::mark`].
+    pub fn into_spans(self) -> Vec<Span> {
+        self.spans
+    }
+}
+
+/// A position in a [`TokenStream`], see [`TokenStream::checkpoint`].
+#[derive(Debug, Clone)]
+pub struct Checkpoint<'a> {
+    source: Source<'a>,
+    next: Option<ParseResult<Spanned<Token<'a>>>>,
+    consumed: usize,
+    /// Number of spans marked so far.
+    spans: usize,
+    open: Vec<Spanned<char>>,
 }
@@ -0,0 +1,251 @@
+use crate::{
+    eval::{EvalErrorKind, EvalResult},
+    expr::BinaryOp,
+};
+use num::{bigint::Sign, BigInt, One, Zero};
+use std::fmt;
+
+/// The result of evaluating an expression.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Int(#[cfg_attr(feature = "serde", serde(with = "bigint_str"))] BigInt),
+    /// Arbitrary-precision binary float, see [`Environment::set_precision`].
+    ///
+    /// [`Environment::set_precision`]: crate::eval::Environment::set_precision
+    #[cfg(feature = "float")]
+    Float(float::BigFloat),
+    /// Text produced by builtins such as `vars()`; not valid in arithmetic.
+    Str(String),
+}
+
+impl Value {
+    pub fn as_int(&self) -> EvalResult<&BigInt> {
+        match self {
+            Value::Int(n) => Ok(n),
+            _ => Err(EvalErrorKind::ExpectedInteger.into()),
+        }
+    }
+    /// Applies `op` to `self` and `rhs`. Integers stay exact; if either side is
+    /// a float, both are converted and the result is rounded to `precision` bits.
+    pub fn binary(self, op: BinaryOp, rhs: Value, precision: usize) -> EvalResult<Value> {
+        self.binary_with(op, rhs, precision, &|| Ok(()))
+    }
+    /// Like [`Value::binary`], but calls `check` between the steps of integer
+    /// powers and stops with its error.
+    #[cfg_attr(not(feature = "float"), allow(unused_variables))]
+    pub(crate) fn binary_with(
+        self,
+        op: BinaryOp,
+        rhs: Value,
+        precision: usize,
+        check: &dyn Fn() -> EvalResult<()>,
+    ) -> EvalResult<Value> {
+        match (self, rhs) {
+            (Value::Int(l), Value::Int(r)) => int_binary(op, l, r, check).map(Value::Int),
+            #[cfg(feature = "float")]
+            (l, r) => float::binary(
+                op,
+                l.to_float(precision)?,
+                r.to_float(precision)?,
+                precision,
+            )
+            .map(Value::Float),
+            #[cfg(not(feature = "float"))]
+            _ => Err(EvalErrorKind::TypeMismatch.into()),
+        }
+    }
+    /// Rough number of bytes the value takes up, e.g. to show what a huge
+    /// power costs.
+    pub fn size_hint(&self) -> usize {
+        match self {
+            Value::Int(n) => n.bits().div_ceil(8) as usize,
+            #[cfg(feature = "float")]
+            Value::Float(x) => x.mantissa_max_bit_len().unwrap_or(0).div_ceil(8),
+            Value::Str(s) => s.len(),
+        }
+    }
+    /// Name of the kind of value: `int`, `float` or `str`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            #[cfg(feature = "float")]
+            Value::Float(_) => "float",
+            Value::Str(_) => "str",
+        }
+    }
+    /// Sign of a number, `None` for strings and NaN.
+    pub fn sign(&self) -> Option<Sign> {
+        match self {
+            Value::Int(n) => Some(n.sign()),
+            #[cfg(feature = "float")]
+            Value::Float(x) if x.is_nan() => None,
+            #[cfg(feature = "float")]
+            Value::Float(x) if x.is_zero() => Some(Sign::NoSign),
+            #[cfg(feature = "float")]
+            Value::Float(x) if x.is_negative() => Some(Sign::Minus),
+            #[cfg(feature = "float")]
+            Value::Float(_) => Some(Sign::Plus),
+            Value::Str(_) => None,
+        }
+    }
+    /// Bits of the magnitude of an integer or of the mantissa of a finite
+    /// float, `None` otherwise.
+    pub fn bits(&self) -> Option<u64> {
+        match self {
+            Value::Int(n) => Some(n.bits()),
+            #[cfg(feature = "float")]
+            Value::Float(x) => x.precision().map(|precision| precision as u64),
+            Value::Str(_) => None,
+        }
+    }
+    pub fn negate(self) -> EvalResult<Value> {
+        match self {
+            Value::Int(n) => Ok(Value::Int(-n)),
+            #[cfg(feature = "float")]
+            Value::Float(f) => Ok(Value::Float(f.neg())),
+            Value::Str(_) => Err(EvalErrorKind::TypeMismatch.into()),
+        }
+    }
+    #[cfg(feature = "float")]
+    fn to_float(&self, precision: usize) -> EvalResult<float::BigFloat> {
+        match self {
+            Value::Int(n) => Ok(float::from_int(n, precision)),
+            Value::Float(f) => Ok(f.clone()),
+            Value::Str(_) => Err(EvalErrorKind::TypeMismatch.into()),
+        }
+    }
+}
+
+fn int_binary(
+    op: BinaryOp,
+    l: BigInt,
+    r: BigInt,
+    check: &dyn Fn() -> EvalResult<()>,
+) -> EvalResult<BigInt> {
+    Ok(match op {
+        BinaryOp::Add => l + r,
+        BinaryOp::Sub => l - r,
+        BinaryOp::Mul => l * r,
+        BinaryOp::Div | BinaryOp::Rem if r.is_zero() => Err(EvalErrorKind::DevideByZero)?,
+        BinaryOp::Div => l / r,
+        BinaryOp::Rem => l % r,
+        BinaryOp::Pow => {
+            if let Some(r) = r.to_biguint() {
+                // Square and multiply, so huge powers can be interrupted.
+                let mut n = BigInt::one();
+                for i in (0..r.bits()).rev() {
+                    check()?;
+                    n = &n * &n;
+                    if r.bit(i) {
+                        n *= &l;
+                    }
+                }
+                n
+            } else {
+                Err(EvalErrorKind::NegativePower)?
+            }
+        }
+        BinaryOp::Assign | BinaryOp::Custom(_) => Err(EvalErrorKind::Unimplemented)?,
+    })
+}
+
+/// Compares numbers numerically, so `1024 == 1024.0`.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(l), Value::Int(r)) => l == r,
+            (Value::Str(l), Value::Str(r)) => l == r,
+            #[cfg(feature = "float")]
+            (l, r) => {
+                // Wide enough to convert either integer exactly.
+                let precision = [l, r]
+                    .iter()
+                    .map(|v| match v {
+                        Value::Int(n) => n.bits() as usize + 1,
+                        _ => 0,
+                    })
+                    .max()
+                    .unwrap()
+                    .max(64);
+                match (l.to_float(precision), r.to_float(precision)) {
+                    (Ok(l), Ok(r)) => l.cmp(&r) == Some(0),
+                    _ => false,
+                }
+            }
+            #[cfg(not(feature = "float"))]
+            _ => false,
+        }
+    }
+}
+
+impl From<BigInt> for Value {
+    fn from(n: BigInt) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => n.fmt(f),
+            #[cfg(feature = "float")]
+            // astro-float keeps the point of a whole mantissa, as in `1.e+20`.
+            Value::Float(x) => f.write_str(&x.to_string().replacen(".e", "e", 1)),
+            Value::Str(s) => s.fmt(f),
+        }
+    }
+}
+
+/// Serializes integers as decimal strings, keeping them readable and exact.
+#[cfg(feature = "serde")]
+pub(crate) mod bigint_str {
+    use num::BigInt;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(n: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(n)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(feature = "float")]
+pub(crate) mod float {
+    use super::*;
+    pub use astro_float::BigFloat;
+    use astro_float::{Consts, Radix, RoundingMode};
+    use std::cell::RefCell;
+
+    const RM: RoundingMode = RoundingMode::ToEven;
+
+    thread_local! {
+        static CONSTS: RefCell<Consts> =
+            RefCell::new(Consts::new().expect("failed to allocate float constants cache"));
+    }
+
+    pub fn parse(s: &str, precision: usize) -> BigFloat {
+        CONSTS.with(|cc| BigFloat::parse(s, Radix::Dec, precision, RM, &mut cc.borrow_mut()))
+    }
+
+    pub fn from_int(n: &BigInt, precision: usize) -> BigFloat {
+        parse(&n.to_string(), precision)
+    }
+
+    pub fn binary(op: BinaryOp, l: BigFloat, r: BigFloat, p: usize) -> EvalResult<BigFloat> {
+        Ok(match op {
+            BinaryOp::Add => l.add(&r, p, RM),
+            BinaryOp::Sub => l.sub(&r, p, RM),
+            BinaryOp::Mul => l.mul(&r, p, RM),
+            BinaryOp::Div | BinaryOp::Rem if r.is_zero() => Err(EvalErrorKind::DevideByZero)?,
+            BinaryOp::Div => l.div(&r, p, RM),
+            BinaryOp::Rem => l.rem(&r),
+            BinaryOp::Pow => CONSTS.with(|cc| l.pow(&r, p, RM, &mut cc.borrow_mut())),
+            BinaryOp::Assign | BinaryOp::Custom(_) => Err(EvalErrorKind::Unimplemented)?,
+        })
+    }
+}
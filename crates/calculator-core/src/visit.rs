@@ -0,0 +1,156 @@
+//! Traversal of [`Expr`] trees.
+//!
+//! Implementors override the methods for the variants they care about. The
+//! traversal itself runs from a stack like the other walks of [`Expr`].
+
+use crate::expr::{BinaryOp, Expr, UnaryOp};
+use num::BigInt;
+use std::mem;
+
+/// Walks an expression by reference, calling the `visit_*` method of every
+/// node before those of its children.
+pub trait ExprVisitor {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+    /// Called on every node before the `visit_*` method of its variant. The
+    /// children of the node are skipped if this returns `false`.
+    fn enter(&mut self, _expr: &Expr) -> bool {
+        true
+    }
+    fn visit_int(&mut self, _n: &BigInt) {}
+    #[cfg(feature = "float")]
+    fn visit_float(&mut self, _lit: &str) {}
+    fn visit_binary(&mut self, _lhs: &Expr, _op: BinaryOp, _rhs: &Expr) {}
+    fn visit_unary(&mut self, _op: UnaryOp, _expr: &Expr) {}
+    fn visit_paren(&mut self, _expr: &Expr) {}
+    fn visit_variable(&mut self, _ident: &str) {}
+    fn visit_call(&mut self, _ident: &str, _args: &[Expr]) {}
+}
+
+/// Visits the nodes of `expr` in pre-order.
+pub fn walk_expr<V: ExprVisitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    let mut stack = vec![expr];
+    while let Some(expr) = stack.pop() {
+        if !visitor.enter(expr) {
+            continue;
+        }
+        match expr {
+            Expr::Int(n) => visitor.visit_int(n),
+            #[cfg(feature = "float")]
+            Expr::Float(lit) => visitor.visit_float(lit),
+            Expr::Binary(lhs, op, rhs) => {
+                visitor.visit_binary(lhs, *op, rhs);
+                stack.push(rhs);
+                stack.push(lhs);
+            }
+            Expr::Unary(op, operand) => {
+                visitor.visit_unary(*op, operand);
+                stack.push(operand);
+            }
+            Expr::Paren(operand) => {
+                visitor.visit_paren(operand);
+                stack.push(operand);
+            }
+            Expr::Variable(ident) => visitor.visit_variable(ident),
+            Expr::Call(ident, args) => {
+                visitor.visit_call(ident, args);
+                stack.extend(args.iter().rev());
+            }
+        }
+    }
+}
+
+/// Rebuilds an expression bottom-up, taking ownership of the nodes. Each
+/// `fold_*` method gets the children of its node already folded.
+pub trait ExprFolder {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        fold_expr(self, expr)
+    }
+    fn fold_int(&mut self, n: BigInt) -> Expr {
+        Expr::Int(n)
+    }
+    #[cfg(feature = "float")]
+    fn fold_float(&mut self, lit: String) -> Expr {
+        Expr::Float(lit)
+    }
+    fn fold_binary(&mut self, lhs: Expr, op: BinaryOp, rhs: Expr) -> Expr {
+        Expr::binary(lhs, op, rhs)
+    }
+    fn fold_unary(&mut self, op: UnaryOp, expr: Expr) -> Expr {
+        Expr::unary(op, expr)
+    }
+    fn fold_paren(&mut self, expr: Expr) -> Expr {
+        Expr::Paren(Box::new(expr))
+    }
+    fn fold_variable(&mut self, ident: String) -> Expr {
+        Expr::Variable(ident)
+    }
+    fn fold_call(&mut self, ident: String, args: Vec<Expr>) -> Expr {
+        Expr::Call(ident, args)
+    }
+}
+
+/// A pending step of [`fold_expr`].
+enum Task {
+    Fold(Expr),
+    /// Combines the last folded nodes into a node of the given kind.
+    Binary(BinaryOp),
+    Unary(UnaryOp),
+    Paren,
+    Call(String, usize),
+}
+
+/// Folds the nodes of `expr` in post-order, calling the matching `fold_*`
+/// method of each.
+pub fn fold_expr<F: ExprFolder + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    let mut tasks = vec![Task::Fold(expr)];
+    let mut folded: Vec<Expr> = Vec::new();
+    while let Some(task) = tasks.pop() {
+        // `Expr` implements `Drop`, so children are taken out rather than
+        // moved by destructuring.
+        match task {
+            Task::Fold(mut expr) => match &mut expr {
+                Expr::Int(n) => folded.push(folder.fold_int(mem::take(n))),
+                #[cfg(feature = "float")]
+                Expr::Float(lit) => folded.push(folder.fold_float(mem::take(lit))),
+                Expr::Binary(lhs, op, rhs) => {
+                    tasks.push(Task::Binary(*op));
+                    tasks.push(Task::Fold(mem::take(&mut **rhs)));
+                    tasks.push(Task::Fold(mem::take(&mut **lhs)));
+                }
+                Expr::Unary(op, operand) => {
+                    tasks.push(Task::Unary(*op));
+                    tasks.push(Task::Fold(mem::take(&mut **operand)));
+                }
+                Expr::Paren(operand) => {
+                    tasks.push(Task::Paren);
+                    tasks.push(Task::Fold(mem::take(&mut **operand)));
+                }
+                Expr::Variable(ident) => folded.push(folder.fold_variable(mem::take(ident))),
+                Expr::Call(ident, args) => {
+                    tasks.push(Task::Call(mem::take(ident), args.len()));
+                    tasks.extend(args.drain(..).rev().map(Task::Fold));
+                }
+            },
+            Task::Binary(op) => {
+                let rhs = folded.pop().unwrap();
+                let lhs = folded.pop().unwrap();
+                folded.push(folder.fold_binary(lhs, op, rhs));
+            }
+            Task::Unary(op) => {
+                let operand = folded.pop().unwrap();
+                folded.push(folder.fold_unary(op, operand));
+            }
+            Task::Paren => {
+                let operand = folded.pop().unwrap();
+                folded.push(folder.fold_paren(operand));
+            }
+            Task::Call(ident, len) => {
+                let args = folded.split_off(folded.len() - len);
+                folded.push(folder.fold_call(ident, args));
+            }
+        }
+    }
+    folded.pop().unwrap()
+}
@@ -0,0 +1,68 @@
+//! The command line frontend, run as a separate process.
+#![cfg(feature = "cli")]
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Runs the CLI on `input` piped to stdin and returns its stdout.
+fn run(args: &[&str], input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calculator-core"))
+        .args(["--init", "/dev/null", "--color", "never"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn quiet_prints_one_line_per_input_line() {
+    let dir = std::env::temp_dir().join(format!("calculator-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let session = dir.join("session.json");
+    let session = session.to_str().unwrap();
+    let input = [
+        "1 + 2",
+        ":obase 16",
+        "255",
+        ":obase 10",
+        "",
+        "x = 5",
+        "f(a) = a",
+        ":type x",
+        ":info x",
+        ":whence 3",
+        ":ast 1 + 2 * 3",
+        &format!(":save {}", session),
+        &format!(":load {}", session),
+        ":clear",
+        ":time 2 * 3",
+        "vars()",
+        "fns()",
+        ":help",
+        ":unknown",
+        "1 +",
+        "y",
+        ":reset",
+    ];
+    let output = run(&["--quiet", "--keep-going"], &input.join("\n"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(lines.len(), input.len(), "{:?}", lines);
+    assert_eq!(lines[0], "3");
+    assert_eq!(lines[1], "");
+    assert_eq!(lines[2], "ff");
+    assert_eq!(lines[14], "6");
+    assert_eq!(lines[15], "x = 5");
+}
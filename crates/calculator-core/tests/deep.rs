@@ -0,0 +1,108 @@
+//! Inputs that nest too deeply to handle by recursion.
+
+use calculator_core::{
+    eval::{Environment, Eval, EvalErrorKind, Limits},
+    expr::Expr,
+    parse::{parse_from_str, ParseErrorKind, MAX_NESTING},
+    stmt::Stmt,
+};
+
+const TERMS: usize = 200_000;
+
+fn long_sum() -> Expr {
+    let input = vec!["1"; TERMS].join("+");
+    parse_from_str(&input).unwrap()
+}
+
+#[test]
+fn long_sum_evaluates() {
+    let value = long_sum().eval(&mut Environment::default()).unwrap();
+    assert_eq!(value.to_string(), TERMS.to_string());
+}
+
+#[test]
+fn long_sum_displays() {
+    let expr = long_sum();
+    assert_eq!(expr.to_string().len(), TERMS * 4 - 3);
+    let grouped = expr.grouped().to_string();
+    assert!(grouped.starts_with("(((") && grouped.ends_with(") + 1) + 1"));
+    assert!(expr.to_sexpr().starts_with("(+ (+ "));
+    assert!(expr.to_rpn().ends_with("1 +"));
+    assert!(expr.to_mathml().ends_with("</math>"));
+    let product: Expr = parse_from_str("2*3").unwrap();
+    assert!(product.to_mathml().contains("<mo>×</mo>"));
+}
+
+#[test]
+fn long_sum_folds() {
+    assert_eq!(long_sum().fold_constants(), Expr::int(TERMS as u64));
+    let terms = vec!["x"; TERMS].join("*");
+    let expr: Expr = parse_from_str(&terms).unwrap();
+    assert_eq!(expr.simplify().to_string().len(), TERMS * 4 - 3);
+}
+
+#[test]
+fn deep_nesting_is_rejected() {
+    let n = 1000;
+    for input in [
+        format!("{}1{}", "(".repeat(n), ")".repeat(n)),
+        format!("{}2", "2**".repeat(n)),
+        format!("{}1{}", "f(".repeat(n), ")".repeat(n)),
+    ] {
+        let err = parse_from_str::<Expr>(&input).unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::TooDeep), "{}", err);
+    }
+}
+
+#[test]
+fn long_run_of_signs_evaluates() {
+    let input = format!("{}1", "-".repeat(100_000));
+    let expr: Expr = parse_from_str(&input).unwrap();
+    assert_eq!(expr.to_string(), input);
+    assert_eq!(
+        expr.eval(&mut Environment::default()).unwrap().to_string(),
+        "1"
+    );
+}
+
+#[test]
+fn nesting_below_the_limit_is_accepted() {
+    let n = MAX_NESTING - 1;
+    let input = format!("{}1{}", "(".repeat(n), ")".repeat(n));
+    let expr: Expr = parse_from_str(&input).unwrap();
+    assert_eq!(expr.to_string(), input);
+    assert_eq!(
+        expr.eval(&mut Environment::default()).unwrap().to_string(),
+        "1"
+    );
+}
+
+#[test]
+fn runaway_recursion_exceeds_the_call_depth() {
+    let mut env = Environment::default();
+    let define: Stmt = parse_from_str("f(x) = f(x) + 1").unwrap();
+    define.eval(&mut env).unwrap();
+    let call: Stmt = parse_from_str("f(1)").unwrap();
+    let err = call.eval(&mut env).unwrap_err();
+    assert!(matches!(err.kind, EvalErrorKind::LimitExceeded), "{}", err);
+
+    env.set_limits(Limits {
+        max_call_depth: Some(10),
+        ..env.limits()
+    });
+    // `f10(x)` calls `f9(x)` and so on, 11 calls deep.
+    for i in 0..=10 {
+        let body = if i == 0 {
+            "x".to_string()
+        } else {
+            format!("f{}(x)", i - 1)
+        };
+        let define: Stmt = parse_from_str(&format!("f{}(x) = {}", i, body)).unwrap();
+        define.eval(&mut env).unwrap();
+    }
+    let call: Stmt = parse_from_str("f9(1)").unwrap();
+    assert_eq!(call.eval(&mut env).unwrap().unwrap().to_string(), "1");
+    let call: Stmt = parse_from_str("f10(1)").unwrap();
+    let err = call.eval(&mut env).unwrap_err();
+    assert!(matches!(err.kind, EvalErrorKind::LimitExceeded), "{}", err);
+}
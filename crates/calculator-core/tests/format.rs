@@ -0,0 +1,57 @@
+use calculator_core::{
+    eval::{Environment, Eval},
+    expr::Expr,
+    format::{DigitGrouping, Formatter, Notation},
+    parse::parse_from_str,
+    value::Value,
+};
+
+fn eval(input: &str) -> Value {
+    let expr: Expr = parse_from_str(input).unwrap();
+    expr.eval(&mut Environment::default()).unwrap()
+}
+
+fn grouped(notation: Notation, radix: Option<u32>) -> Formatter {
+    Formatter {
+        grouping: Some(DigitGrouping::default()),
+        notation,
+        radix,
+        ..Formatter::default()
+    }
+}
+
+#[cfg(feature = "float")]
+#[test]
+fn grouping_stops_at_the_exponent() {
+    let formatter = grouped(Notation::Engineering, None);
+    assert_eq!(formatter.format(&eval("150000.0")), "150e+3");
+    assert_eq!(formatter.format(&eval("1500000.0")), "1.5e+6");
+    let formatter = grouped(Notation::Plain, None);
+    assert_eq!(formatter.format(&eval("1234567.125")), "1,234,567.125");
+}
+
+#[test]
+fn grouping_counts_letters_only_above_base_10() {
+    let formatter = grouped(Notation::default(), Some(16));
+    assert_eq!(formatter.format(&eval("16**6 - 1")), "fff,fff");
+    let formatter = grouped(Notation::default(), None);
+    assert_eq!(formatter.format(&eval("1234567")), "1,234,567");
+}
+
+#[test]
+fn invalid_radixes_are_ignored() {
+    for radix in [0, 1, 37] {
+        let formatter = grouped(Notation::default(), Some(radix));
+        assert_eq!(formatter.format(&eval("1234567")), "1,234,567");
+    }
+}
+
+#[cfg(feature = "float")]
+#[test]
+fn whole_mantissas_have_no_point() {
+    let value = eval("10.0 ** 20");
+    assert_eq!(value.to_string(), "1e+20");
+    assert_eq!(Formatter::default().format(&value), "1e+20");
+    assert_eq!(eval("-8.0").to_string(), "-8e+0");
+    assert_eq!(eval("1.5").to_string(), "1.5e+0");
+}
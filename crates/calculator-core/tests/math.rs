@@ -0,0 +1,22 @@
+use calculator_core::math::perfect_power;
+use num::{traits::Pow, BigInt};
+
+fn decompose(n: impl Into<BigInt>) -> Option<(BigInt, u32)> {
+    perfect_power(&n.into(), &|| Ok(())).unwrap()
+}
+
+#[test]
+fn perfect_power_finds_the_largest_exponent() {
+    assert_eq!(decompose(1024), Some((2.into(), 10)));
+    assert_eq!(
+        decompose(Pow::pow(BigInt::from(6), 35u32)),
+        Some((6.into(), 35))
+    );
+    assert_eq!(decompose(-64), Some(((-4).into(), 3)));
+    assert_eq!(decompose(-32), Some(((-2).into(), 5)));
+    assert_eq!(decompose(12), None);
+    assert_eq!(decompose(-4), None);
+    for n in [-1, 0, 1] {
+        assert_eq!(decompose(n), None);
+    }
+}
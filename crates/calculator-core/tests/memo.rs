@@ -0,0 +1,35 @@
+//! Caching of pure function calls.
+
+use calculator_core::{
+    eval::{Environment, Eval},
+    parse::parse_from_str,
+    stmt::Stmt,
+};
+
+fn eval(env: &mut Environment, input: &str) -> Option<String> {
+    let stmt: Stmt = parse_from_str(input).unwrap();
+    stmt.eval(env).unwrap().map(|value| value.to_string())
+}
+
+#[test]
+fn repeated_calls_are_evaluated_once() {
+    let mut env = Environment::default();
+    env.set_memoize(true);
+    eval(&mut env, "f0(n) = n");
+    // Each level calls the one below twice, 2**64 calls without the cache.
+    for level in 1..=64 {
+        let input = format!("f{0}(n) = f{1}(n) + f{1}(n)", level, level - 1);
+        eval(&mut env, &input);
+    }
+    assert_eq!(eval(&mut env, "f64(1)").unwrap(), "18446744073709551616");
+}
+
+#[cfg(feature = "float")]
+#[test]
+fn integer_and_float_arguments_are_cached_apart() {
+    let mut env = Environment::default();
+    env.set_memoize(true);
+    eval(&mut env, "half(n) = n / 2");
+    assert_eq!(eval(&mut env, "half(3)").unwrap(), "1");
+    assert_eq!(eval(&mut env, "half(3.0)").unwrap(), "1.5e+0");
+}
@@ -0,0 +1,43 @@
+//! Saving and loading an [`Environment`] and expressions as JSON.
+#![cfg(feature = "cli")]
+
+use calculator_core::{
+    eval::{Environment, Eval},
+    expr::Expr,
+    parse::parse_from_str,
+    stmt::Stmt,
+};
+
+fn eval(env: &mut Environment, input: &str) -> String {
+    let stmt: Stmt = parse_from_str(input).unwrap();
+    stmt.eval(env).unwrap().unwrap().to_string()
+}
+
+#[test]
+fn environment_round_trips() {
+    let mut env = Environment::default();
+    eval(&mut env, "x = 5");
+    let stmt: Stmt = parse_from_str("f(a) = a * x").unwrap();
+    stmt.eval(&mut env).unwrap();
+    let json = serde_json::to_string(&env).unwrap();
+    let mut env: Environment = serde_json::from_str(&json).unwrap();
+    assert_eq!(eval(&mut env, "f(2)"), "10");
+}
+
+#[test]
+fn unparsable_functions_are_reported() {
+    let json = r#"{"variables": {}, "functions": {"f": {"params": ["a"], "body": "a // 2"}}}"#;
+    let err = serde_json::from_str::<Environment>(json).unwrap_err();
+    assert!(err.to_string().starts_with("function `f`: "), "{}", err);
+}
+
+#[test]
+fn expressions_serialize_as_their_tree() {
+    let expr: Expr = parse_from_str("1 + x").unwrap();
+    let json = serde_json::to_value(&expr).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({ "Binary": [{ "Int": "1" }, "Add", { "Variable": "x" }] })
+    );
+    assert_eq!(serde_json::from_value::<Expr>(json).unwrap(), expr);
+}
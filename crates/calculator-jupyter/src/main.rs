@@ -0,0 +1,334 @@
+//! A Jupyter kernel for the calculator. Each notebook starts a kernel of its
+//! own, so it has one environment for all of its cells.
+
+mod wire;
+
+use anyhow::{anyhow, Context};
+use calculator_core::{
+    builtins::BUILTINS,
+    diagnostic::Diagnostic,
+    eval::{CancelToken, Environment, Eval, EvalOptions},
+    format::Formatter,
+    parse::{is_incomplete, parse_with_spans, script_inputs},
+    stmt::Stmt,
+};
+use clap::Parser;
+use serde_json::{json, Value};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
+use unicode_ident::is_xid_continue;
+use wire::{ConnectionInfo, Message, Session, PROTOCOL_VERSION};
+
+/// Time left to send the reply to a shutdown request before exiting.
+const SHUTDOWN_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+enum Command {
+    /// Install the kernel for the current user, so that Jupyter lists it
+    Install,
+    /// Run the kernel on the ports of CONNECTION_FILE, as Jupyter does
+    Run { connection_file: PathBuf },
+}
+
+struct Kernel {
+    session: Session,
+    shell: zmq::Socket,
+    iopub: zmq::Socket,
+    env: Environment,
+    formatter: Formatter,
+    execution_count: u64,
+    /// Cancels the evaluation in progress on an interrupt request.
+    cancel_token: CancelToken,
+}
+
+impl Kernel {
+    fn run(&mut self) -> anyhow::Result<()> {
+        loop {
+            let request = match self.session.recv(&self.shell) {
+                Ok(request) => request,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    continue;
+                }
+            };
+            let publish = |kernel: &Self, state| {
+                let status = (kernel.session).broadcast(
+                    &request,
+                    "status",
+                    json!({ "execution_state": state }),
+                );
+                kernel.session.send(&kernel.iopub, &status)
+            };
+            publish(self, "busy")?;
+            let reply = match request.msg_type() {
+                "kernel_info_request" => Some(("kernel_info_reply", kernel_info())),
+                "execute_request" => Some(("execute_reply", self.execute(&request)?)),
+                "is_complete_request" => Some(("is_complete_reply", is_complete(&request))),
+                "complete_request" => Some(("complete_reply", self.complete(&request))),
+                "comm_info_request" => {
+                    Some(("comm_info_reply", json!({ "status": "ok", "comms": {} })))
+                }
+                "shutdown_request" => shutdown(&self.session, &self.shell, &request),
+                _ => None,
+            };
+            if let Some((msg_type, content)) = reply {
+                let reply = self.session.reply(&request, msg_type, content);
+                self.session.send(&self.shell, &reply)?;
+            }
+            publish(self, "idle")?;
+        }
+    }
+    /// Evaluates the inputs of a cell, publishing the results of all of them
+    /// together or the first error, and returns the content of the reply.
+    fn execute(&mut self, request: &Message) -> anyhow::Result<Value> {
+        let code = request.content["code"].as_str().unwrap_or_default();
+        if !request.content["silent"].as_bool().unwrap_or(false) {
+            self.execution_count += 1;
+        }
+        let count = self.execution_count;
+        let input = json!({ "code": code, "execution_count": count });
+        let input = self.session.broadcast(request, "execute_input", input);
+        self.session.send(&self.iopub, &input)?;
+        self.cancel_token.reset();
+        let mut results = Vec::new();
+        let mut error = None;
+        for input in script_inputs(code) {
+            match self.eval(&input) {
+                Ok(Some(result)) => results.push(result),
+                Ok(None) => {}
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+        }
+        if !results.is_empty() {
+            let data = json!({ "text/plain": results.join("\n") });
+            let content = json!({ "execution_count": count, "data": data, "metadata": {} });
+            let result = self.session.broadcast(request, "execute_result", content);
+            self.session.send(&self.iopub, &result)?;
+        }
+        Ok(match error {
+            Some((ename, evalue)) => {
+                let content = json!({ "ename": ename, "evalue": evalue, "traceback": [evalue] });
+                let error = self.session.broadcast(request, "error", content);
+                self.session.send(&self.iopub, &error)?;
+                json!({
+                    "status": "error",
+                    "execution_count": count,
+                    "ename": ename,
+                    "evalue": evalue,
+                    "traceback": [evalue],
+                })
+            }
+            None => json!({ "status": "ok", "execution_count": count, "user_expressions": {} }),
+        })
+    }
+    /// The formatted value of `input`, or the name and the rendered message of
+    /// its error.
+    fn eval(&mut self, input: &str) -> Result<Option<String>, (&'static str, String)> {
+        let (stmt, spans) = parse_with_spans::<Stmt>(input)
+            .map_err(|err| ("SyntaxError", Diagnostic::new(&err, err.span).render(input)))?;
+        let options = EvalOptions::default().with_cancel_token(self.cancel_token.clone());
+        let value = (self.env)
+            .transaction(|env| stmt.eval_with(env, options))
+            .map_err(|err| {
+                (
+                    "EvalError",
+                    Diagnostic::new(&err, err.span(&spans)).render(input),
+                )
+            })?;
+        Ok(value.map(|value| self.formatter.format(&value)))
+    }
+    /// The names in scope that start with the identifier before the cursor.
+    fn complete(&self, request: &Message) -> Value {
+        let code = request.content["code"].as_str().unwrap_or_default();
+        // In Unicode code points, as of version 5.2 of the protocol.
+        let cursor = request.content["cursor_pos"].as_u64().unwrap_or_default() as usize;
+        let end = code
+            .char_indices()
+            .nth(cursor)
+            .map_or(code.len(), |(i, _)| i);
+        let start = code[..end]
+            .rfind(|c: char| !is_xid_continue(c))
+            .map_or(0, |i| i + code[i..].chars().next().unwrap().len_utf8());
+        let prefix = &code[start..end];
+        let variables = self.env.variables().map(|(ident, _)| ident.to_string());
+        let functions = (self.env.functions().map(|(ident, _)| ident))
+            .chain(BUILTINS.iter().map(|builtin| builtin.name))
+            .map(|ident| format!("{}(", ident));
+        let mut matches: Vec<_> = (variables.chain(functions))
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        matches.sort();
+        matches.dedup();
+        json!({
+            "status": "ok",
+            "matches": matches,
+            "cursor_start": code[..start].chars().count(),
+            "cursor_end": cursor,
+            "metadata": {},
+        })
+    }
+}
+
+fn kernel_info() -> Value {
+    json!({
+        "status": "ok",
+        "protocol_version": PROTOCOL_VERSION,
+        "implementation": "calculator",
+        "implementation_version": env!("CARGO_PKG_VERSION"),
+        "language_info": {
+            "name": "calc",
+            "version": env!("CARGO_PKG_VERSION"),
+            "mimetype": "text/plain",
+            "file_extension": ".calc",
+        },
+        "banner": "calculator",
+        "help_links": [],
+    })
+}
+
+/// Whether the frontend should submit `code` on Enter, rather than start a
+/// new line, as at the prompt of the CLI.
+fn is_complete(request: &Message) -> Value {
+    let code = request.content["code"].as_str().unwrap_or_default();
+    match script_inputs(code).last() {
+        Some(input) if is_incomplete(input) => json!({ "status": "incomplete", "indent": "" }),
+        _ => json!({ "status": "complete" }),
+    }
+}
+
+/// Replies to a shutdown request and exits.
+fn shutdown(
+    session: &Session,
+    socket: &zmq::Socket,
+    request: &Message,
+) -> Option<(&'static str, Value)> {
+    let restart = request.content["restart"].as_bool().unwrap_or(false);
+    let content = json!({ "status": "ok", "restart": restart });
+    let reply = session.reply(request, "shutdown_reply", content);
+    if let Err(err) = session.send(socket, &reply) {
+        eprintln!("error: {}", err);
+    }
+    // Sockets send in the background, which exiting would cut short.
+    thread::sleep(SHUTDOWN_DELAY);
+    process::exit(0)
+}
+
+/// Answers interrupt and shutdown requests, which come on the control socket
+/// so that they get through while a cell is evaluated.
+fn serve_control(session: Session, control: zmq::Socket, cancel_token: CancelToken) {
+    loop {
+        let request = match session.recv(&control) {
+            Ok(request) => request,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                continue;
+            }
+        };
+        let content = match request.msg_type() {
+            "interrupt_request" => {
+                cancel_token.cancel();
+                json!({ "status": "ok" })
+            }
+            "shutdown_request" => {
+                shutdown(&session, &control, &request);
+                continue;
+            }
+            "kernel_info_request" => kernel_info(),
+            _ => continue,
+        };
+        let msg_type = request.msg_type().replace("_request", "_reply");
+        let reply = session.reply(&request, &msg_type, content);
+        if let Err(err) = session.send(&control, &reply) {
+            eprintln!("error: {}", err);
+        }
+    }
+}
+
+/// Echoes the heartbeats of the frontend, which tell it the kernel is alive.
+fn serve_heartbeat(heartbeat: zmq::Socket) -> anyhow::Result<()> {
+    loop {
+        let message = heartbeat.recv_bytes(0)?;
+        heartbeat.send(message, 0)?;
+    }
+}
+
+fn run(connection_file: &Path) -> anyhow::Result<()> {
+    let info = fs::read_to_string(connection_file)
+        .with_context(|| format!("failed to read `{}`", connection_file.display()))?;
+    let info: ConnectionInfo = serde_json::from_str(&info)?;
+    let session = Session::new(&info)?;
+    let context = zmq::Context::new();
+    let bind = |socket_type, port| -> anyhow::Result<zmq::Socket> {
+        let socket = context.socket(socket_type)?;
+        socket.bind(&info.endpoint(port))?;
+        Ok(socket)
+    };
+    let shell = bind(zmq::ROUTER, info.shell_port)?;
+    let iopub = bind(zmq::PUB, info.iopub_port)?;
+    let control = bind(zmq::ROUTER, info.control_port)?;
+    let heartbeat = bind(zmq::REP, info.hb_port)?;
+    // Input requests are not supported, but frontends expect the socket.
+    let _stdin = bind(zmq::ROUTER, info.stdin_port)?;
+    let cancel_token = CancelToken::new();
+    thread::spawn({
+        let session = session.clone();
+        let cancel_token = cancel_token.clone();
+        move || serve_control(session, control, cancel_token)
+    });
+    thread::spawn(move || serve_heartbeat(heartbeat));
+    let mut kernel = Kernel {
+        session,
+        shell,
+        iopub,
+        env: Environment::default(),
+        formatter: Formatter::default(),
+        execution_count: 0,
+        cancel_token,
+    };
+    kernel.run()
+}
+
+/// Writes the kernel spec to the kernels directory of Jupyter.
+fn install() -> anyhow::Result<()> {
+    let data_dir = match env::var_os("JUPYTER_DATA_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = env::home_dir().ok_or_else(|| anyhow!("no home directory"))?;
+            if cfg!(target_os = "macos") {
+                home.join("Library/Jupyter")
+            } else if cfg!(windows) {
+                let appdata = env::var_os("APPDATA").context("APPDATA is not set")?;
+                PathBuf::from(appdata).join("jupyter")
+            } else {
+                home.join(".local/share/jupyter")
+            }
+        }
+    };
+    let dir = data_dir.join("kernels").join("calculator");
+    fs::create_dir_all(&dir)?;
+    let spec = json!({
+        "argv": [env::current_exe()?, "run", "{connection_file}"],
+        "display_name": "Calculator",
+        "language": "calc",
+        "interrupt_mode": "message",
+    });
+    let path = dir.join("kernel.json");
+    fs::write(&path, serde_json::to_string_pretty(&spec)?)?;
+    println!("installed the kernel spec to `{}`", path.display());
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    match Command::parse() {
+        Command::Install => install(),
+        Command::Run { connection_file } => run(&connection_file),
+    }
+}
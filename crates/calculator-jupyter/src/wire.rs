@@ -0,0 +1,237 @@
+//! Messages of the Jupyter protocol as they are sent over ZeroMQ, see
+//! <https://jupyter-client.readthedocs.io/en/stable/messaging.html>.
+
+use anyhow::{bail, Context};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Version of the messaging protocol the kernel implements.
+pub const PROTOCOL_VERSION: &str = "5.3";
+
+/// Separates the routing identities from the rest of a message.
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// The file Jupyter starts the kernel with, naming the ports to bind and the
+/// key to sign messages with.
+#[derive(Debug, Deserialize)]
+pub struct ConnectionInfo {
+    pub transport: String,
+    pub ip: String,
+    pub shell_port: u16,
+    pub iopub_port: u16,
+    pub stdin_port: u16,
+    pub control_port: u16,
+    pub hb_port: u16,
+    pub key: String,
+    pub signature_scheme: String,
+}
+
+impl ConnectionInfo {
+    pub fn endpoint(&self, port: u16) -> String {
+        format!("{}://{}:{}", self.transport, self.ip, port)
+    }
+}
+
+/// A message to or from the frontend.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// Routing prefix of the sender, which replies are sent back with.
+    identities: Vec<Vec<u8>>,
+    pub header: Value,
+    pub parent_header: Value,
+    pub content: Value,
+}
+
+impl Message {
+    pub fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or_default()
+    }
+}
+
+/// Signs and checks messages, and gives them the headers of this kernel.
+#[derive(Clone)]
+pub struct Session {
+    /// `None` if the connection file has an empty key, which disables signing.
+    key: Option<Hmac<Sha256>>,
+    id: String,
+}
+
+impl Session {
+    pub fn new(info: &ConnectionInfo) -> anyhow::Result<Self> {
+        let key = match (info.key.as_str(), info.signature_scheme.as_str()) {
+            ("", _) => None,
+            (key, "hmac-sha256") => Some(Hmac::new_from_slice(key.as_bytes())?),
+            (_, scheme) => bail!("unsupported signature scheme `{}`", scheme),
+        };
+        Ok(Self {
+            key,
+            id: Uuid::new_v4().to_string(),
+        })
+    }
+    /// A message of `msg_type` in reply to `parent`, to the same sender.
+    pub fn reply(&self, parent: &Message, msg_type: &str, content: Value) -> Message {
+        Message {
+            identities: parent.identities.clone(),
+            header: self.header(msg_type),
+            parent_header: parent.header.clone(),
+            content,
+        }
+    }
+    /// A message of `msg_type` caused by `parent` for every frontend, to be
+    /// sent on the IOPub socket.
+    pub fn broadcast(&self, parent: &Message, msg_type: &str, content: Value) -> Message {
+        Message {
+            identities: vec![msg_type.as_bytes().to_vec()],
+            header: self.header(msg_type),
+            parent_header: parent.header.clone(),
+            content,
+        }
+    }
+    fn header(&self, msg_type: &str) -> Value {
+        json!({
+            "msg_id": Uuid::new_v4().to_string(),
+            "session": self.id,
+            "username": "kernel",
+            "date": chrono::Utc::now().to_rfc3339(),
+            "msg_type": msg_type,
+            "version": PROTOCOL_VERSION,
+        })
+    }
+    /// The signature of the parts of a message, `None` if signing is
+    /// disabled.
+    fn mac(&self, parts: &[&[u8]]) -> Option<Hmac<Sha256>> {
+        let mut mac = self.key.clone()?;
+        for part in parts {
+            mac.update(part);
+        }
+        Some(mac)
+    }
+    pub fn recv(&self, socket: &zmq::Socket) -> anyhow::Result<Message> {
+        let frames = socket.recv_multipart(0)?;
+        let delimiter = (frames.iter())
+            .position(|frame| frame == DELIMITER)
+            .context("message without delimiter")?;
+        let (identities, frames) = frames.split_at(delimiter);
+        let [_, signature, header, parent_header, metadata, content, ..] = frames else {
+            bail!("message with missing parts");
+        };
+        if let Some(mac) = self.mac(&[header, parent_header, metadata, content]) {
+            let signature = hex::decode(signature).ok();
+            if signature.is_none_or(|signature| mac.verify_slice(&signature).is_err()) {
+                bail!("message with an invalid signature");
+            }
+        }
+        Ok(Message {
+            identities: identities.to_vec(),
+            header: serde_json::from_slice(header)?,
+            parent_header: serde_json::from_slice(parent_header)?,
+            content: serde_json::from_slice(content)?,
+        })
+    }
+    pub fn send(&self, socket: &zmq::Socket, message: &Message) -> anyhow::Result<()> {
+        let header = serde_json::to_vec(&message.header)?;
+        let parent_header = serde_json::to_vec(&message.parent_header)?;
+        let metadata = b"{}".to_vec();
+        let content = serde_json::to_vec(&message.content)?;
+        let signature = (self.mac(&[&header, &parent_header, &metadata, &content]))
+            .map_or(String::new(), |mac| {
+                hex::encode(mac.finalize().into_bytes())
+            });
+        let mut frames = message.identities.clone();
+        frames.push(DELIMITER.to_vec());
+        frames.push(signature.into_bytes());
+        frames.extend([header, parent_header, metadata, content]);
+        socket.send_multipart(frames, 0)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(key: &str) -> Session {
+        let info = ConnectionInfo {
+            transport: "inproc".to_string(),
+            ip: "test".to_string(),
+            shell_port: 0,
+            iopub_port: 0,
+            stdin_port: 0,
+            control_port: 0,
+            hb_port: 0,
+            key: key.to_string(),
+            signature_scheme: "hmac-sha256".to_string(),
+        };
+        Session::new(&info).unwrap()
+    }
+
+    /// Two connected sockets, which pass the frames of a message unchanged.
+    fn sockets(context: &zmq::Context, name: &str) -> (zmq::Socket, zmq::Socket) {
+        let endpoint = format!("inproc://{}", name);
+        let (a, b) = (context.socket(zmq::PAIR), context.socket(zmq::PAIR));
+        let (a, b) = (a.unwrap(), b.unwrap());
+        a.bind(&endpoint).unwrap();
+        b.connect(&endpoint).unwrap();
+        (a, b)
+    }
+
+    fn request() -> Message {
+        Message {
+            identities: vec![b"frontend".to_vec()],
+            header: json!({ "msg_id": "1", "msg_type": "execute_request" }),
+            parent_header: json!({}),
+            content: json!({ "code": "1 + 2" }),
+        }
+    }
+
+    #[test]
+    fn signed_messages_round_trip() {
+        let context = zmq::Context::new();
+        let (a, b) = sockets(&context, "round-trip");
+        let session = session("secret");
+        let reply = session.reply(&request(), "execute_reply", json!({ "status": "ok" }));
+        session.send(&a, &reply).unwrap();
+        let received = session.recv(&b).unwrap();
+        assert_eq!(received.identities, [b"frontend".to_vec()]);
+        assert_eq!(received.msg_type(), "execute_reply");
+        assert_eq!(received.header, reply.header);
+        assert_eq!(received.parent_header["msg_id"], "1");
+        assert_eq!(received.content, json!({ "status": "ok" }));
+    }
+
+    #[test]
+    fn messages_signed_with_another_key_are_refused() {
+        let context = zmq::Context::new();
+        let (a, b) = sockets(&context, "other-key");
+        session("other").send(&a, &request()).unwrap();
+        assert!(session("secret").recv(&b).is_err());
+    }
+
+    #[test]
+    fn tampered_messages_are_refused() {
+        let context = zmq::Context::new();
+        let (a, b) = sockets(&context, "tampered");
+        let session = session("secret");
+        session.send(&a, &request()).unwrap();
+        let mut frames = b.recv_multipart(0).unwrap();
+        *frames.last_mut().unwrap() = br#"{"code": "2 + 2"}"#.to_vec();
+        b.send_multipart(frames, 0).unwrap();
+        assert!(session.recv(&a).is_err());
+    }
+
+    #[test]
+    fn an_empty_key_disables_signing() {
+        let context = zmq::Context::new();
+        let (a, b) = sockets(&context, "unsigned");
+        let session = session("");
+        session.send(&a, &request()).unwrap();
+        let frames = b.recv_multipart(0).unwrap();
+        let delimiter = frames.iter().position(|frame| frame == DELIMITER).unwrap();
+        assert!(frames[delimiter + 1].is_empty());
+        b.send_multipart(frames, 0).unwrap();
+        assert_eq!(session.recv(&a).unwrap().content, request().content);
+    }
+}
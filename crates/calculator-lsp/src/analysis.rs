@@ -0,0 +1,252 @@
+//! Language features of `.calc` scripts, on plain text and byte offsets.
+//! Scripts hold one statement per line, as for `calculator FILE`.
+
+use calculator_core::{
+    builtins::{Builtin, BUILTINS},
+    diagnostic::Diagnostic,
+    eval::{Environment, Eval, EvalOptions},
+    expr::{BinaryOp, Expr},
+    format::Formatter,
+    parse::{parse_script, parse_with_spans},
+    stmt::Stmt,
+    token::Span,
+    visit::ExprVisitor,
+};
+use std::time::Duration;
+
+/// Give up on evaluating a hovered subexpression, which may be as slow as any
+/// other.
+const HOVER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Hovered values longer than this many characters are truncated.
+const HOVER_LENGTH: usize = 200;
+
+/// The syntax errors of `text`, with spans into it.
+pub fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    parse_script(text).err().unwrap_or_default()
+}
+
+/// What [`hover`] shows: code, and the doc of a function.
+pub struct Hover {
+    pub span: Span,
+    pub code: String,
+    pub doc: Option<String>,
+}
+
+/// The definition and doc of the function whose name is at `offset` in a
+/// call, e.g. `f(x) = x * 2` for an offset in `f(3)`.
+///
+/// Otherwise, the largest subexpression at `offset` that doesn't depend on
+/// the environment, with its value or error, e.g. `2 * 3` and `6` for an
+/// offset in `x + 2 * 3`. Lone integers are left out, as they are their own
+/// value.
+pub fn hover(text: &str, offset: usize) -> Option<Hover> {
+    let (line_start, line) = line_at(text, offset);
+    let (stmt, spans) = parse_with_spans::<Stmt>(line).ok()?;
+    // The spans of definitions start with those of the parameters and of the
+    // call they were parsed as.
+    let (expr, first) = match &stmt {
+        Stmt::Expr(expr) => (expr, 0),
+        Stmt::Define { params, body, .. } => (body, params.len() + 1),
+    };
+    let nodes = post_order(expr);
+    let offset = offset - line_start;
+    let call = (nodes.iter().zip(&spans[first..])).find_map(|(expr, span)| match expr {
+        Expr::Call(ident, _) if (span.start..=span.start + ident.len()).contains(&offset) => {
+            Some((ident, span.start))
+        }
+        _ => None,
+    });
+    if let Some((ident, start)) = call {
+        let completion = (completions(text).into_iter())
+            .find(|completion| completion.function && completion.name == *ident)?;
+        let start = line_start + start;
+        return Some(Hover {
+            span: Span::new(start, start + ident.len()),
+            code: completion.detail,
+            doc: completion.doc,
+        });
+    }
+    let (expr, span) = (nodes.into_iter().zip(&spans[first..]))
+        .filter(|(expr, span)| span.start <= offset && offset <= span.end && is_constant(expr))
+        .max_by_key(|(_, span)| span.end - span.start)?;
+    if matches!(expr, Expr::Int(_)) {
+        return None;
+    }
+    let formatter = Formatter {
+        max_length: Some(HOVER_LENGTH),
+        ..Formatter::default()
+    };
+    let options = EvalOptions::default().with_timeout(HOVER_TIMEOUT);
+    let value = match expr.eval_with(&mut Environment::default(), options) {
+        Ok(value) => formatter.format(&value),
+        Err(err) => err.to_string(),
+    };
+    Some(Hover {
+        span: Span::new(line_start + span.start, line_start + span.end),
+        code: format!("{} = {}", expr, value),
+        doc: None,
+    })
+}
+
+/// A name to complete.
+pub struct Completion {
+    pub name: String,
+    pub function: bool,
+    /// The definition, e.g. `f(x) = x * 2` or `gcd(a, b)`.
+    pub detail: String,
+    pub doc: Option<String>,
+}
+
+/// The builtins and the variables and functions defined in `text`.
+pub fn completions(text: &str) -> Vec<Completion> {
+    let mut completions: Vec<_> = BUILTINS.iter().map(builtin_completion).collect();
+    for line in text.lines() {
+        let Ok((stmt, _)) = parse_with_spans::<Stmt>(line) else {
+            continue;
+        };
+        let completion = match stmt {
+            Stmt::Define {
+                ident,
+                params,
+                body,
+                doc,
+            } => Completion {
+                detail: format!("{}({}) = {}", ident, params.join(", "), body),
+                name: ident,
+                function: true,
+                doc,
+            },
+            Stmt::Expr(Expr::Binary(ref lhs, BinaryOp::Assign, _)) => match &**lhs {
+                Expr::Variable(ident) => Completion {
+                    detail: line.trim().to_string(),
+                    name: ident.clone(),
+                    function: false,
+                    doc: None,
+                },
+                _ => continue,
+            },
+            Stmt::Expr(_) => continue,
+        };
+        // Later definitions replace earlier ones.
+        completions.retain(|other| other.name != completion.name);
+        completions.push(completion);
+    }
+    completions
+}
+
+fn builtin_completion(builtin: &Builtin) -> Completion {
+    Completion {
+        name: builtin.name.to_string(),
+        function: true,
+        detail: format!("{}({})", builtin.name, builtin.params.join(", ")),
+        doc: Some(builtin.doc.to_string()),
+    }
+}
+
+/// The start and the text of the line holding `offset`.
+fn line_at(text: &str, offset: usize) -> (usize, &str) {
+    let start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+    (start, text[start..end].trim_end_matches('\r'))
+}
+
+/// The nodes of `expr` in post-order, the order of their spans. This is the
+/// reverse of a pre-order walk that visits the last child first.
+fn post_order(expr: &Expr) -> Vec<&Expr> {
+    let mut nodes = Vec::new();
+    let mut stack = vec![expr];
+    while let Some(expr) = stack.pop() {
+        nodes.push(expr);
+        match expr {
+            Expr::Binary(lhs, _, rhs) => stack.extend([&**lhs, &**rhs]),
+            Expr::Unary(_, expr) | Expr::Paren(expr) => stack.push(expr),
+            Expr::Call(_, args) => stack.extend(args),
+            _ => {}
+        }
+    }
+    nodes.reverse();
+    nodes
+}
+
+/// Whether `expr` has no variables, assignments or calls of functions other
+/// than pure builtins.
+fn is_constant(expr: &Expr) -> bool {
+    let mut checker = ConstantChecker(true);
+    checker.visit_expr(expr);
+    checker.0
+}
+
+struct ConstantChecker(bool);
+
+impl ExprVisitor for ConstantChecker {
+    fn visit_binary(&mut self, _lhs: &Expr, op: BinaryOp, _rhs: &Expr) {
+        if op == BinaryOp::Assign {
+            self.0 = false;
+        }
+    }
+    fn visit_variable(&mut self, _ident: &str) {
+        self.0 = false;
+    }
+    fn visit_call(&mut self, ident: &str, _args: &[Expr]) {
+        if !Builtin::get(ident).is_some_and(|builtin| builtin.pure) {
+            self.0 = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The hover at the first occurrence of `at` in `text`.
+    fn hover_at(text: &str, at: &str) -> Hover {
+        hover(text, text.find(at).unwrap()).unwrap()
+    }
+
+    fn spanned(text: &str, span: Span) -> &str {
+        &text[span.start..span.end]
+    }
+
+    #[test]
+    fn hover_shows_constant_subexpressions() {
+        let text = "x = 1\ny = x + 2 * 3\n";
+        let hover = hover_at(text, "3");
+        assert_eq!(hover.code, "2 * 3 = 6");
+        assert_eq!(spanned(text, hover.span), "2 * 3");
+        assert!(super::hover(text, text.find("x +").unwrap()).is_none());
+    }
+
+    #[test]
+    fn hover_skips_the_parameters_of_definitions() {
+        let text = "f(a, b) = a * (2 + 3) + b\n";
+        let hover = hover_at(text, "2 +");
+        assert_eq!(hover.code, "(2 + 3) = 5");
+        assert_eq!(spanned(text, hover.span), "(2 + 3)");
+    }
+
+    #[test]
+    fn hover_shows_the_definition_of_called_functions() {
+        let text = "f(a) = a * 2 ## doubles a\ny = f(3) + pow(2, 3)\n";
+        let hover = hover_at(text, "f(3)");
+        assert_eq!(hover.code, "f(a) = a * 2");
+        assert_eq!(hover.doc.as_deref(), Some("doubles a"));
+        assert_eq!(spanned(text, hover.span), "f");
+        let hover = hover_at(text, "pow");
+        assert_eq!(hover.code, "pow(x, y)");
+        assert_eq!(spanned(text, hover.span), "pow");
+    }
+
+    #[test]
+    fn completions_keep_the_last_definition() {
+        let text = "x = 1\nf(a) = a\nx = 2\nf(a, b) = a + b\n";
+        let completions = completions(text);
+        let x: Vec<_> = completions.iter().filter(|c| c.name == "x").collect();
+        assert_eq!(x.len(), 1);
+        assert_eq!(x[0].detail, "x = 2");
+        assert!(!x[0].function);
+        let f = completions.iter().find(|c| c.name == "f").unwrap();
+        assert_eq!(f.detail, "f(a, b) = a + b");
+        assert!(completions.iter().any(|c| c.name == "pow" && c.function));
+    }
+}
@@ -0,0 +1,287 @@
+//! A language server for `.calc` scripts, talking LSP over stdin and stdout.
+
+mod analysis;
+
+use anyhow::anyhow;
+use calculator_core::token::Span;
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{Completion, HoverRequest, Request as _},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, Documentation, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, MarkupContent, MarkupKind, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+
+/// The open documents and their text.
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, String>,
+}
+
+impl Server {
+    fn handle_request(&self, request: Request) -> anyhow::Result<Response> {
+        let result = match request.method.as_str() {
+            HoverRequest::METHOD => {
+                let params: HoverParams = match serde_json::from_value(request.params) {
+                    Ok(params) => params,
+                    Err(err) => return Ok(invalid_params(request.id, err)),
+                };
+                let position = params.text_document_position_params;
+                serde_json::to_value(self.hover(&position.text_document.uri, position.position))?
+            }
+            Completion::METHOD => {
+                let params: CompletionParams = match serde_json::from_value(request.params) {
+                    Ok(params) => params,
+                    Err(err) => return Ok(invalid_params(request.id, err)),
+                };
+                let uri = params.text_document_position.text_document.uri;
+                serde_json::to_value(self.completion(&uri))?
+            }
+            _ => {
+                return Ok(Response::new_err(
+                    request.id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request `{}`", request.method),
+                ))
+            }
+        };
+        Ok(Response::new_ok(request.id, result))
+    }
+    /// Updates the documents, returning the diagnostics to publish.
+    /// Notifications with malformed params are logged and ignored.
+    fn handle_notification(
+        &mut self,
+        notification: Notification,
+    ) -> Option<PublishDiagnosticsParams> {
+        let Notification { method, params } = notification;
+        let (uri, version) = match method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = notification_params(&method, params)?;
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), document.text);
+                (document.uri, Some(document.version))
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = notification_params(&method, params)?;
+                // The whole text, as only full syncs are offered.
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    let uri = params.text_document.uri.clone();
+                    self.documents.insert(uri, change.text);
+                }
+                (params.text_document.uri, Some(params.text_document.version))
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = notification_params(&method, params)?;
+                self.documents.remove(&params.text_document.uri);
+                // Clears the diagnostics of the document.
+                return Some(PublishDiagnosticsParams::new(
+                    params.text_document.uri,
+                    Vec::new(),
+                    None,
+                ));
+            }
+            _ => return None,
+        };
+        // A change without text to a document that was never opened.
+        let text = self.documents.get(&uri)?;
+        let diagnostics = (analysis::diagnostics(text).into_iter())
+            .map(|diagnostic| lsp_types::Diagnostic {
+                range: range(text, diagnostic.span.unwrap_or(Span::new(0, 0))),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("calc".to_string()),
+                message: diagnostic.message,
+                ..Default::default()
+            })
+            .collect();
+        Some(PublishDiagnosticsParams::new(uri, diagnostics, version))
+    }
+    fn hover(&self, uri: &Url, position: Position) -> Option<Hover> {
+        let text = self.documents.get(uri)?;
+        let hover = analysis::hover(text, offset(text, position))?;
+        let mut value = format!("```\n{}\n```", hover.code);
+        if let Some(doc) = hover.doc {
+            value.push_str("\n\n");
+            value.push_str(&doc);
+        }
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(range(text, hover.span)),
+        })
+    }
+    fn completion(&self, uri: &Url) -> Option<CompletionResponse> {
+        let text = self.documents.get(uri)?;
+        let items = (analysis::completions(text).into_iter())
+            .map(|completion| CompletionItem {
+                kind: Some(match completion.function {
+                    true => CompletionItemKind::FUNCTION,
+                    false => CompletionItemKind::VARIABLE,
+                }),
+                // Like the completion of the CLI, which opens the call.
+                insert_text: completion.function.then(|| format!("{}(", completion.name)),
+                label: completion.name,
+                detail: Some(completion.detail),
+                documentation: completion.doc.map(Documentation::String),
+                ..Default::default()
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+}
+
+/// The byte offset of `position`, whose character counts UTF-16 code units as
+/// LSP does by default. Positions past the end of a line or of the text are
+/// moved back to it.
+fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if c == '\n' || units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn position(text: &str, offset: usize) -> Position {
+    let offset = offset.min(text.len());
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    Position::new(
+        text[..line_start].matches('\n').count() as u32,
+        text[line_start..offset].encode_utf16().count() as u32,
+    )
+}
+
+fn range(text: &str, span: Span) -> Range {
+    Range::new(position(text, span.start), position(text, span.end))
+}
+
+/// The params of a notification, or `None` after logging why they don't
+/// match its method.
+fn notification_params<P: DeserializeOwned>(method: &str, params: serde_json::Value) -> Option<P> {
+    match serde_json::from_value(params) {
+        Ok(params) => Some(params),
+        Err(err) => {
+            eprintln!("error: invalid params of `{}`: {}", method, err);
+            None
+        }
+    }
+}
+
+/// Answers a request whose parameters don't match its method, leaving the
+/// server running.
+fn invalid_params(id: RequestId, err: serde_json::Error) -> Response {
+    Response::new_err(
+        id,
+        lsp_server::ErrorCode::InvalidParams as i32,
+        err.to_string(),
+    )
+}
+
+fn main() -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        ..Default::default()
+    };
+    connection
+        .initialize(serde_json::to_value(capabilities)?)
+        .map_err(|err| anyhow!(err))?;
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection
+                    .handle_shutdown(&request)
+                    .map_err(|err| anyhow!(err))?
+                {
+                    break;
+                }
+                let response = server.handle_request(request)?;
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(params) = server.handle_notification(notification) {
+                    let notification =
+                        Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+                    connection
+                        .sender
+                        .send(Message::Notification(notification))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    // Lets the writer thread finish.
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_count_utf16_units() {
+        // `é` takes 2 bytes and 1 unit, `😀` 4 bytes and 2 units.
+        let text = "é😀 = 1\nx = é😀\n";
+        let x = text.find('x').unwrap();
+        assert_eq!(position(text, x), Position::new(1, 0));
+        assert_eq!(offset(text, Position::new(1, 0)), x);
+        let equals = text.find('=').unwrap();
+        assert_eq!(position(text, equals), Position::new(0, 4));
+        assert_eq!(offset(text, Position::new(0, 4)), equals);
+        let end = text.len() - 1;
+        assert_eq!(position(text, end), Position::new(1, 7));
+        assert_eq!(offset(text, Position::new(1, 7)), end);
+    }
+
+    #[test]
+    fn positions_past_the_end_are_clamped() {
+        let text = "1 + 2\n3";
+        assert_eq!(offset(text, Position::new(0, 99)), 5);
+        assert_eq!(offset(text, Position::new(5, 0)), text.len());
+        assert_eq!(position(text, 99), Position::new(1, 1));
+    }
+
+    #[test]
+    fn malformed_params_keep_the_server_running() {
+        let mut server = Server::default();
+        let request = Request::new(1.into(), HoverRequest::METHOD.to_string(), 0);
+        let response = server.handle_request(request).unwrap();
+        let error = response.error.unwrap();
+        assert_eq!(error.code, lsp_server::ErrorCode::InvalidParams as i32);
+        let method = DidOpenTextDocument::METHOD.to_string();
+        assert!(server
+            .handle_notification(Notification::new(method, 0))
+            .is_none());
+        let method = DidChangeTextDocument::METHOD.to_string();
+        let params = serde_json::json!({
+            "textDocument": { "uri": "file:///a.calc", "version": 2 },
+            "contentChanges": [],
+        });
+        assert!(server
+            .handle_notification(Notification::new(method, params))
+            .is_none());
+    }
+}
@@ -0,0 +1,158 @@
+//! The calculator for Python, through PyO3. See the README for an example.
+
+use calculator_core::{
+    diagnostic::Diagnostic,
+    eval::{CancelToken, Environment, Eval, EvalOptions},
+    format::{Formatter, Notation},
+    parse::parse_with_spans,
+    stmt::Stmt,
+    value::Value,
+};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::{PyDict, PyFloat},
+};
+use std::{panic, thread, time::Duration};
+
+/// How often a running evaluation checks for Ctrl-C.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Stack of the thread evaluating. Parsing recurses on brackets and evaluation
+/// on calls of user-defined functions, both up to their limits in
+/// `calculator_core`, which take a few megabytes at most in debug builds.
+const STACK_SIZE: usize = 16 << 20;
+
+create_exception!(
+    calculator,
+    CalculatorError,
+    PyException,
+    "Base class of the errors of `Calculator.eval`."
+);
+create_exception!(
+    calculator,
+    ParseError,
+    CalculatorError,
+    "The input is not a valid statement."
+);
+create_exception!(
+    calculator,
+    EvalError,
+    CalculatorError,
+    "The evaluation failed, e.g. on a division by zero."
+);
+
+/// An environment to evaluate inputs in, keeping the variables and functions
+/// they define.
+#[pyclass(module = "calculator")]
+#[derive(Debug, Default)]
+struct Calculator {
+    env: Environment,
+}
+
+#[pymethods]
+impl Calculator {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+    /// Evaluates a statement, returning its value, or `None` for statements
+    /// without one such as definitions. Raises `ParseError` or `EvalError`,
+    /// and leaves the environment unchanged, if the statement is invalid or
+    /// fails, or takes longer than `timeout` seconds. Ctrl-C interrupts it
+    /// with `KeyboardInterrupt`.
+    #[pyo3(signature = (input, timeout = None))]
+    fn eval<'py>(
+        &mut self,
+        py: Python<'py>,
+        input: &str,
+        timeout: Option<f64>,
+    ) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let (stmt, spans) = parse_with_spans::<Stmt>(input)
+            .map_err(|err| ParseError::new_err(Diagnostic::new(&err, err.span).render(input)))?;
+        let cancel_token = CancelToken::new();
+        let mut options = EvalOptions::default().with_cancel_token(cancel_token.clone());
+        if let Some(timeout) = timeout {
+            let timeout = Duration::try_from_secs_f64(timeout)
+                .map_err(|err| PyValueError::new_err(err.to_string()))?;
+            options = options.with_timeout(timeout);
+        }
+        let env = &mut self.env;
+        let caller = thread::current();
+        let (result, interrupt) = thread::scope(|scope| {
+            let evaluation =
+                thread::Builder::new()
+                    .stack_size(STACK_SIZE)
+                    .spawn_scoped(scope, move || {
+                        let result = env.transaction(|env| stmt.eval_with(env, options));
+                        caller.unpark();
+                        result
+                    })?;
+            // Lets other threads run Python meanwhile, and the signal handlers
+            // in between.
+            let mut interrupt = None;
+            while !evaluation.is_finished() {
+                py.detach(|| thread::park_timeout(POLL_INTERVAL));
+                if let (None, Err(err)) = (&interrupt, py.check_signals()) {
+                    cancel_token.cancel();
+                    interrupt = Some(err);
+                }
+            }
+            let result = evaluation
+                .join()
+                .unwrap_or_else(|payload| panic::resume_unwind(payload));
+            PyResult::Ok((result, interrupt))
+        })?;
+        if let Some(err) = interrupt {
+            return Err(err);
+        }
+        let value = result.map_err(|err| {
+            EvalError::new_err(Diagnostic::new(&err, err.span(&spans)).render(input))
+        })?;
+        value.map(|value| to_python(py, &value)).transpose()
+    }
+    /// A dict of the variables and their values.
+    fn variables<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let variables = PyDict::new(py);
+        for (ident, value) in self.env.variables() {
+            variables.set_item(ident, to_python(py, value)?)?;
+        }
+        Ok(variables)
+    }
+    /// Forgets all variables and functions.
+    fn reset(&mut self) {
+        self.env = Environment::default();
+    }
+}
+
+/// Integers as `int`, floats as the simplest `Fraction` that rounds to them,
+/// or as `float` if they are infinite or NaN.
+fn to_python<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    match value {
+        Value::Int(n) => Ok(n.into_pyobject(py)?.into_any()),
+        Value::Float(_) => {
+            let formatter = Formatter {
+                notation: Notation::Fraction,
+                ..Formatter::default()
+            };
+            let fraction = formatter.format(value);
+            let fractions = py.import("fractions")?;
+            match fractions.getattr("Fraction")?.call1((&fraction,)) {
+                Ok(fraction) => Ok(fraction),
+                Err(_) => Ok(py.get_type::<PyFloat>().call1((&fraction,))?),
+            }
+        }
+        Value::Str(s) => Ok(s.into_pyobject(py)?.into_any()),
+    }
+}
+
+#[pymodule]
+fn calculator(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_class::<Calculator>()?;
+    m.add("CalculatorError", py.get_type::<CalculatorError>())?;
+    m.add("ParseError", py.get_type::<ParseError>())?;
+    m.add("EvalError", py.get_type::<EvalError>())?;
+    Ok(())
+}
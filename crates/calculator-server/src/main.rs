@@ -0,0 +1,349 @@
+use anyhow::anyhow;
+use calculator_core::{
+    diagnostic::Diagnostic,
+    eval::{Environment, Eval, EvalOptions},
+    format::Formatter,
+    parse::{parse_with_spans, script_inputs},
+    stmt::Stmt,
+};
+use clap::Parser;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    io::Read,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tungstenite::{
+    handshake::derive_accept_key,
+    protocol::{Role, WebSocketConfig},
+    Message, WebSocket,
+};
+
+/// Longest accepted input, in bytes.
+const MAX_INPUT: u64 = 1 << 16;
+
+/// Stack of the threads serving connections. Parsing recurses on brackets and
+/// evaluation on calls of user-defined functions, both up to their limits in
+/// `calculator_core`, which take a few megabytes at most in debug builds.
+const STACK_SIZE: usize = 16 << 20;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+    /// Give up on evaluations after this many seconds
+    #[arg(long, value_name = "SECS", default_value = "10")]
+    timeout: u64,
+    /// Keep the environments of at most N sessions, forgetting the least
+    /// recently used one for a new one
+    #[arg(long, value_name = "N", default_value = "1000")]
+    max_sessions: usize,
+    /// Evaluate at most N inputs at once, queueing the others [default: the
+    /// number of CPUs]
+    #[arg(long, value_name = "N")]
+    max_evaluations: Option<usize>,
+    /// Serve at most N connections at once, answering others with 503
+    #[arg(long, value_name = "N", default_value = "256")]
+    max_connections: usize,
+}
+
+/// Bounds the number of evaluations running at once.
+struct Semaphore {
+    permits: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Semaphore {
+    fn new(permits: usize) -> Self {
+        Self {
+            permits: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+    /// Runs `f` once a permit is free.
+    fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        let permits = self.permits.lock().unwrap();
+        let mut permits = (self.released)
+            .wait_while(permits, |permits| *permits == 0)
+            .unwrap();
+        *permits -= 1;
+        drop(permits);
+        let _permit = Permit(self);
+        f()
+    }
+}
+
+/// Returns its permit to the semaphore when dropped, even on panics.
+struct Permit<'a>(&'a Semaphore);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.permits.lock().unwrap() += 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// Environments of the named sessions, by token.
+struct Sessions {
+    sessions: Mutex<HashMap<String, Session>>,
+    max_sessions: usize,
+}
+
+struct Session {
+    /// Locked for the whole evaluation, so inputs of one session run one at a
+    /// time.
+    env: Arc<Mutex<Environment>>,
+    last_used: Instant,
+}
+
+impl Sessions {
+    fn new(max_sessions: usize) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            max_sessions,
+        }
+    }
+    /// The environment of `token`, new if it is unknown.
+    fn get(&self, token: &str) -> Arc<Mutex<Environment>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if !sessions.contains_key(token) && sessions.len() >= self.max_sessions {
+            let oldest = (sessions.iter())
+                .min_by_key(|(_, session)| session.last_used)
+                .map(|(token, _)| token.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+        let session = sessions
+            .entry(token.to_string())
+            .or_insert_with(|| Session {
+                env: Arc::default(),
+                last_used: Instant::now(),
+            });
+        session.last_used = Instant::now();
+        session.env.clone()
+    }
+}
+
+struct Evaluator {
+    sessions: Sessions,
+    formatter: Formatter,
+    timeout: Duration,
+    evaluations: Semaphore,
+}
+
+impl Evaluator {
+    fn handle(&self, mut request: Request) -> anyhow::Result<()> {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let token = (query.split('&'))
+            .find_map(|param| param.strip_prefix("session="))
+            .filter(|token| !token.is_empty())
+            .map(str::to_string);
+        let response = match (request.method(), path) {
+            (Method::Post, "/eval") => {
+                // One byte more than accepted tells a long input from one
+                // that fits exactly.
+                let mut input = Vec::new();
+                (request.as_reader())
+                    .take(MAX_INPUT + 1)
+                    .read_to_end(&mut input)?;
+                if input.len() as u64 > MAX_INPUT {
+                    Response::from_string("input is too long").with_status_code(413)
+                } else if let Ok(input) = String::from_utf8(input) {
+                    let output = match token {
+                        Some(token) => {
+                            self.eval(&mut self.sessions.get(&token).lock().unwrap(), &input)
+                        }
+                        None => self.eval(&mut Environment::default(), &input),
+                    };
+                    Response::from_string(output.to_string()).with_header(json_header())
+                } else {
+                    Response::from_string("input is not UTF-8").with_status_code(400)
+                }
+            }
+            (Method::Get, "/repl") => return self.repl(request),
+            (_, "/eval" | "/repl") => {
+                Response::from_string("method not allowed").with_status_code(405)
+            }
+            _ => Response::from_string("not found").with_status_code(404),
+        };
+        Ok(request.respond(response)?)
+    }
+    /// Upgrades `request` to a WebSocket that evaluates each line of the text
+    /// messages it receives in an environment of its own, sending the result
+    /// of each line as soon as it is evaluated.
+    fn repl(&self, request: Request) -> anyhow::Result<()> {
+        let key = (request.headers().iter())
+            .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+            .map(|header| header.value.as_str().to_string());
+        let Some(key) = key else {
+            let response =
+                Response::from_string("expected a WebSocket handshake").with_status_code(400);
+            return Ok(request.respond(response)?);
+        };
+        let accept = derive_accept_key(key.as_bytes());
+        let response = Response::empty(101)
+            .with_header(Header::from_bytes("Sec-WebSocket-Accept", accept).unwrap());
+        let stream = request.upgrade("websocket", response);
+        let config = WebSocketConfig::default()
+            .max_message_size(Some(MAX_INPUT as usize))
+            .max_frame_size(Some(MAX_INPUT as usize));
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, Some(config));
+        let mut env = Environment::default();
+        loop {
+            let input = match socket.read() {
+                Ok(Message::Text(input)) => input,
+                // Pings are answered and closes acknowledged by reading on.
+                Ok(_) => continue,
+                Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+            for input in script_inputs(&input) {
+                let output = self.eval(&mut env, &input);
+                socket.send(Message::text(output.to_string()))?;
+            }
+        }
+    }
+    /// The result of `input` as `{"input": ..., "result": ..., "error": ...}`,
+    /// like the `--json` output of the CLI.
+    fn eval(&self, env: &mut Environment, input: &str) -> serde_json::Value {
+        let input = input.trim_end();
+        let result = self.evaluations.run(|| {
+            parse_with_spans::<Stmt>(input)
+                .map_err(|err| Diagnostic::new(&err, err.span).render(input))
+                .and_then(|(stmt, spans)| {
+                    let options = EvalOptions::default().with_timeout(self.timeout);
+                    env.transaction(|env| stmt.eval_with(env, options))
+                        .map_err(|err| Diagnostic::new(&err, err.span(&spans)).render(input))
+                })
+        });
+        let (value, error) = match result {
+            Ok(value) => (value.map(|value| self.formatter.format(&value)), None),
+            Err(err) => (None, Some(err)),
+        };
+        json!({ "input": input, "result": value, "error": error })
+    }
+}
+
+fn json_header() -> Header {
+    Header::from_bytes("Content-Type", "application/json").unwrap()
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let http = Server::http(args.listen).map_err(|err| anyhow!(err))?;
+    let max_evaluations = args.max_evaluations.unwrap_or_else(|| {
+        thread::available_parallelism().map_or(1, |parallelism| parallelism.get())
+    });
+    let evaluator = Arc::new(Evaluator {
+        sessions: Sessions::new(args.max_sessions),
+        formatter: Formatter::default(),
+        timeout: Duration::from_secs(args.timeout),
+        evaluations: Semaphore::new(max_evaluations),
+    });
+    let connections = Arc::new(AtomicUsize::new(0));
+    eprintln!("listening on http://{}", args.listen);
+    for request in http.incoming_requests() {
+        if connections.fetch_add(1, Ordering::SeqCst) >= args.max_connections {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let response = Response::from_string("too many connections").with_status_code(503);
+            let _ = request.respond(response);
+            continue;
+        }
+        let (evaluator, open) = (evaluator.clone(), connections.clone());
+        let spawned = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                if let Err(err) = evaluator.handle(request) {
+                    eprintln!("error: {}", err);
+                }
+                open.fetch_sub(1, Ordering::SeqCst);
+            });
+        if let Err(err) = spawned {
+            eprintln!("error: {}", err);
+            connections.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, net::TcpStream};
+
+    fn evaluator() -> Evaluator {
+        Evaluator {
+            sessions: Sessions::new(1),
+            formatter: Formatter::default(),
+            timeout: Duration::from_secs(1),
+            evaluations: Semaphore::new(1),
+        }
+    }
+
+    /// Sends `body` to `POST /eval` of a server handled by `evaluator` and
+    /// returns the status line and body of the response.
+    fn post(evaluator: &Evaluator, body: &str) -> (String, String) {
+        let http = Server::http("127.0.0.1:0").unwrap();
+        let addr = http.server_addr().to_ip().unwrap();
+        let request = format!(
+            "POST /eval HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            addr,
+            body.len(),
+            body,
+        );
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        evaluator.handle(http.recv().unwrap()).unwrap();
+        let response = client.join().unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    #[test]
+    fn eval_returns_the_result_or_the_error() {
+        let (evaluator, mut env) = (evaluator(), Environment::default());
+        assert_eq!(
+            evaluator.eval(&mut env, "1 + 2\n"),
+            json!({ "input": "1 + 2", "result": "3", "error": null }),
+        );
+        assert_eq!(
+            evaluator.eval(&mut env, "x = 5"),
+            json!({ "input": "x = 5", "result": "5", "error": null }),
+        );
+        let output = evaluator.eval(&mut env, "x / 0");
+        assert_eq!(output["input"], "x / 0");
+        assert_eq!(output["result"], serde_json::Value::Null);
+        assert!(output["error"].is_string(), "{}", output);
+    }
+
+    #[test]
+    fn long_inputs_are_refused() {
+        let evaluator = evaluator();
+        let fits = "1".to_string() + &" ".repeat(MAX_INPUT as usize - 1);
+        let (status, body) = post(&evaluator, &fits);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap()["result"],
+            "1"
+        );
+        let (status, body) = post(&evaluator, &format!("{} ", fits));
+        assert_eq!(status, "HTTP/1.1 413 Payload Too Large");
+        assert_eq!(body, "input is too long");
+    }
+}
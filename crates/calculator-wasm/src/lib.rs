@@ -0,0 +1,98 @@
+//! The calculator for JavaScript, through wasm-bindgen:
+//!
+//! ```js
+//! import init, { Calculator } from "./calculator_wasm.js";
+//!
+//! await init();
+//! const calculator = Calculator.new();
+//! calculator.eval("x = 2**100");
+//! calculator.eval("x + 1"); // "1267650600228229401496703205377"
+//! calculator.variables(); // { x: "1267650600228229401496703205376" }
+//! calculator.set_timeout(1000);
+//! calculator.eval(input); // throws if it takes over a second
+//! ```
+
+use calculator_core::{
+    diagnostic::Diagnostic,
+    eval::{Environment, Eval, EvalOptions},
+    format::Formatter,
+    parse::parse_with_spans,
+    stmt::Stmt,
+};
+use js_sys::{Object, Reflect};
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+
+/// An environment to evaluate inputs in, keeping the variables and functions
+/// they define.
+#[wasm_bindgen]
+#[derive(Debug, Default)]
+pub struct Calculator {
+    env: Environment,
+    formatter: Formatter,
+    timeout: Option<Duration>,
+}
+
+#[wasm_bindgen]
+impl Calculator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Evaluates a statement, returning its value, or `undefined` for
+    /// statements without one such as definitions. Throws an `Error` whose
+    /// message is rendered with the input, and leaves the environment
+    /// unchanged, if the statement is invalid or fails.
+    pub fn eval(&mut self, input: &str) -> Result<Option<String>, JsError> {
+        let (stmt, spans) = parse_with_spans::<Stmt>(input)
+            .map_err(|err| JsError::new(&Diagnostic::new(&err, err.span).render(input)))?;
+        let mut options = EvalOptions::default();
+        if let Some(timeout) = self.timeout {
+            options = options.with_timeout(timeout);
+        }
+        let value = self
+            .env
+            .transaction(|env| stmt.eval_with(env, options))
+            .map_err(|err| JsError::new(&Diagnostic::new(&err, err.span(&spans)).render(input)))?;
+        Ok(value.map(|value| self.formatter.format(&value)))
+    }
+    /// Makes `eval` throw once an evaluation takes longer than `millis`
+    /// milliseconds, as it blocks the thread it runs on until then. `undefined`
+    /// removes the limit, which is the default.
+    pub fn set_timeout(&mut self, millis: Option<u32>) {
+        self.timeout = millis.map(|millis| Duration::from_millis(millis.into()));
+    }
+    /// The value of a variable, or `undefined` if it is not defined.
+    pub fn variable(&self, ident: &str) -> Option<String> {
+        let value = self.env.get_variable(ident).ok()?;
+        Some(self.formatter.format(value))
+    }
+    /// An object of the variables and their values.
+    pub fn variables(&self) -> Object {
+        let variables = Object::new();
+        for (ident, value) in self.env.variables() {
+            let value = self.formatter.format(value);
+            Reflect::set(&variables, &ident.into(), &value.into()).unwrap();
+        }
+        variables
+    }
+    /// An object of the defined functions and their definitions, e.g.
+    /// `{ f: "f(x) = x * 2" }`. Builtins are not included.
+    pub fn functions(&self) -> Object {
+        let functions = Object::new();
+        for (ident, function) in self.env.functions() {
+            let params = function
+                .params()
+                .map_or("...".to_string(), |params| params.join(", "));
+            let body = function
+                .body()
+                .map_or(String::new(), |body| format!(" = {}", body));
+            let definition = format!("{}({}){}", ident, params, body);
+            Reflect::set(&functions, &ident.into(), &definition.into()).unwrap();
+        }
+        functions
+    }
+    /// Forgets all variables and functions.
+    pub fn reset(&mut self) {
+        self.env = Environment::default();
+    }
+}
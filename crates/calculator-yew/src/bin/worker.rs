@@ -0,0 +1,12 @@
+use calculator_yew::{EvalWorker, Json};
+use gloo_worker::Registrable;
+
+#[cfg(feature = "wee_alloc")]
+#[global_allocator]
+static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
+
+fn main() {
+    #[cfg(feature = "panic-hook")]
+    console_error_panic_hook::set_once();
+    EvalWorker::registrar().encoding::<Json>().register();
+}
@@ -0,0 +1,103 @@
+//! Evaluation in a web worker, so that long computations leave the page
+//! responsive. The app sends each input with the environment, and the worker
+//! binary sends back the result with the changed environment.
+//!
+//! A [`CancelToken`](calculator_core::eval::CancelToken) cannot be shared with
+//! the worker, so cancelling abandons it for a new one instead.
+
+use calculator_core::{
+    diagnostic::Diagnostic,
+    eval::{Environment, Eval, EvalOptions},
+    parse::parse_with_spans,
+    stmt::Stmt,
+    value::Value,
+};
+use gloo_worker::{Codec, HandlerId, Worker, WorkerScope};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::time::Duration;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::DedicatedWorkerGlobalScope;
+
+/// Give up on an evaluation, which bounds how long an abandoned worker keeps
+/// computing.
+const EVAL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An input to evaluate in `env`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    /// Returned in the [`Response`], to tell it from those of abandoned
+    /// requests.
+    pub id: u64,
+    pub input: String,
+    pub env: Environment,
+}
+
+/// The result of a [`Request`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Response {
+    pub id: u64,
+    /// The environment after the evaluation, unchanged if it failed.
+    pub env: Environment,
+    /// The value, or the error rendered with the input.
+    pub result: Result<Option<Value>, String>,
+}
+
+/// Evaluates each [`Request`] on its own, keeping no state between them.
+pub struct EvalWorker;
+
+impl Worker for EvalWorker {
+    type Message = ();
+    type Input = Request;
+    type Output = Response;
+
+    fn create(_scope: &WorkerScope<Self>) -> Self {
+        Self
+    }
+
+    fn update(&mut self, _scope: &WorkerScope<Self>, _msg: Self::Message) {}
+
+    fn received(&mut self, scope: &WorkerScope<Self>, request: Self::Input, id: HandlerId) {
+        let Request {
+            id: request_id,
+            input,
+            mut env,
+        } = request;
+        let result = match parse_with_spans::<Stmt>(&input) {
+            Ok((stmt, spans)) => {
+                let options = EvalOptions::default().with_timeout(EVAL_TIMEOUT);
+                env.transaction(|env| stmt.eval_with(env, options))
+                    .map_err(|err| Diagnostic::new(&err, err.span(&spans)).render(&input))
+            }
+            Err(err) => Err(Diagnostic::new(&err, err.span).render(&input)),
+        };
+        let response = Response {
+            id: request_id,
+            env,
+            result,
+        };
+        scope.respond(id, response);
+    }
+
+    /// Closes the worker once the app abandons it, as its only bridge is gone.
+    fn disconnected(&mut self, _scope: &WorkerScope<Self>, _id: HandlerId) {
+        js_sys::global()
+            .unchecked_into::<DedicatedWorkerGlobalScope>()
+            .close();
+    }
+}
+
+/// Messages to and from the worker as JSON, which unlike the default bincode
+/// reads everything the environment writes.
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<I: Serialize>(input: I) -> JsValue {
+        let json = serde_json::to_string(&input).expect("worker messages serialize to JSON");
+        JsValue::from_str(&json)
+    }
+
+    fn decode<O: DeserializeOwned>(input: JsValue) -> O {
+        let json = input.as_string().expect("worker messages are strings");
+        serde_json::from_str(&json).expect("worker messages deserialize from JSON")
+    }
+}
@@ -1,13 +1,90 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use calculator_core::{
-    eval::{Environment, Eval},
-    expr::Expr,
-    parse::parse_from_str,
+    builtins::BUILTINS,
+    diagnostic::Diagnostic,
+    eval::{Environment, EvalErrorKind, Function, Snapshot},
+    format::{DigitGrouping, Formatter},
+    hint::hints,
+    history::History,
+    parse::{is_incomplete, parse_script, parse_with_spans, script_inputs},
+    stmt::Stmt,
+    token::{Lexer, Span, Token},
 };
+use calculator_yew::{EvalWorker, Json, Request, Response};
 use gloo_timers::callback::Timeout;
-use wasm_bindgen::JsCast;
-use web_sys::HtmlInputElement;
+use gloo_worker::{Spawnable, WorkerBridge};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{HashSet, VecDeque},
+    mem,
+    ops::{Range, RangeInclusive},
+};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    Blob, BlobPropertyBag, Element, File, HtmlAnchorElement, HtmlInputElement, HtmlTextAreaElement,
+    Storage, Url,
+};
 use yew::prelude::*;
 
+#[cfg(feature = "wee_alloc")]
+#[global_allocator]
+static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
+
+/// Script of the worker binary, next to the page.
+const WORKER_PATH: &str = "worker.js";
+
+/// Service worker that caches the app for offline use, next to the page.
+const SERVICE_WORKER_PATH: &str = "sw.js";
+
+/// Outputs rendered at first, and added by each "show earlier" click, so long
+/// transcripts stay quick to render.
+const SHOWN_OUTPUTS: usize = 500;
+
+/// Results longer than this many characters are collapsed to their ends
+/// until expanded.
+const COLLAPSE_LENGTH: usize = 1000;
+
+/// Characters kept at each end of a collapsed result.
+const COLLAPSED_END: usize = 40;
+
+/// Steps of [`Preferences::font_size`], in percent.
+const FONT_SIZE_STEP: u32 = 10;
+const FONT_SIZES: RangeInclusive<u32> = 50..=300;
+
+/// Distance in pixels from the bottom of the page within which new outputs
+/// keep it scrolled to the bottom.
+const SCROLL_SLACK: i32 = 40;
+
+/// Evaluations that can be undone.
+const UNDO_LIMIT: usize = 100;
+
+/// Key of the saved session in localStorage, see [`Tab::key`].
+const STORAGE_KEY: &str = "calculator-session";
+
+/// Key of the [`Tab`]s and the index of the open one in localStorage.
+const TABS_KEY: &str = "calculator-tabs";
+
+/// Key of the chosen [`Theme`] in localStorage.
+const THEME_KEY: &str = "calculator-theme";
+
+/// Key of the [`Formatter`] settings in localStorage.
+const FORMATTER_KEY: &str = "calculator-formatter";
+
+/// Key of the [`Preferences`] in localStorage.
+const PREFERENCES_KEY: &str = "calculator-preferences";
+
+/// Start of a URL fragment holding the inputs of a shared session.
+const SHARE_PREFIX: &str = "#session=";
+
+/// Name of the file [`Msg::Export`] downloads.
+const EXPORT_FILE: &str = "calculator-session.md";
+
+/// Largest decompressed size of shared inputs, against crafted links.
+const SHARE_LIMIT: usize = 1 << 20;
+
 enum Msg {
     ClickEvent,
     Focus,
@@ -15,30 +92,253 @@ enum Msg {
     InputChanged(InputEvent),
     KeyboardEvent(KeyboardEvent),
     SelectionChangeEvent(Event),
+    Copy(String),
+    Clear,
+    Reset,
+    ToggleTheme,
+    ToggleSettings,
+    Share,
+    Export,
+    NewTab,
+    SelectTab(usize),
+    CloseTab(usize),
+    ShowEarlier,
+    Undo,
+    ToggleVariables,
+    /// A name in the variables panel was clicked, to be inserted at the caret.
+    InsertName(String),
+    Evaluated(Box<Response>),
+    Cancel,
+    OpenSearch,
+    CloseSearch,
+    /// The text to search for changed.
+    SearchInput(String),
+    /// Go to the next match, or the previous one if `false`.
+    SearchStep(bool),
+    /// Expand or collapse the long result at the index in the outputs.
+    ToggleExpanded(usize),
+    /// A past input was clicked, to be edited again.
+    EditInput(String),
+    /// A script was dropped on the page, to run its lines.
+    RunScript(String),
+    /// The field of a setting changed to the given text.
+    SetSetting(Setting, String),
+    ToggleTypeset,
+    ToggleHints,
+    /// Make the text larger, smaller if `Some(false)`, or the default size.
+    Zoom(Option<bool>),
+}
+
+/// Settings of the page itself, beside those of the [`Formatter`].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct Preferences {
+    /// Whether to show inputs as typeset math too.
+    typeset: bool,
+    /// Whether to note the precedence of ambiguous inputs, e.g. `-2 ** 2`.
+    hints: bool,
+    /// Size of the outputs and the input, in percent of the default.
+    font_size: u32,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            typeset: false,
+            hints: true,
+            font_size: 100,
+        }
+    }
+}
+
+/// A setting of how results are written, edited in the settings panel.
+#[derive(Debug, Clone, Copy)]
+enum Setting {
+    Grouping,
+    Radix,
+    Precision,
+}
+
+/// A session of its own outputs, history and environment, saved apart from
+/// the others.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Tab {
+    id: u32,
+    name: String,
+}
+
+impl Tab {
+    fn new(id: u32) -> Self {
+        Self {
+            id,
+            name: format!("Session {}", id + 1),
+        }
+    }
+    /// Key of the session in localStorage. The first one keeps the key from
+    /// before there were tabs.
+    fn key(&self) -> String {
+        match self.id {
+            0 => STORAGE_KEY.to_string(),
+            id => format!("{}-{}", STORAGE_KEY, id),
+        }
+    }
+}
+
+/// The session before an evaluation, returned to by [`Msg::Undo`].
+struct UndoStep {
+    snapshot: Snapshot,
+    outputs: usize,
+    history: usize,
+}
+
+/// Search of the outputs, opened with Ctrl+F.
+struct Search {
+    query: String,
+    /// Index in the outputs of the match gone to.
+    current: Option<usize>,
+}
+
+/// An input being evaluated by the worker.
+struct Pending {
+    id: u64,
+    input: String,
+    /// Number of outputs before those of the input.
+    outputs: usize,
+}
+
+/// Colors of the page, set as `data-theme` on the root element for the CSS
+/// variables in `index.scss`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    fn as_str(self) -> &'static str {
+        match self {
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+    /// The theme chosen before, or the one the browser prefers.
+    fn load() -> Self {
+        let saved = local_storage().and_then(|storage| storage.get_item(THEME_KEY).ok()?);
+        match saved.as_deref() {
+            Some("light") => Theme::Light,
+            Some("dark") => Theme::Dark,
+            _ => {
+                let dark = web_sys::window()
+                    .and_then(|window| window.match_media("(prefers-color-scheme: dark)").ok()?)
+                    .is_some_and(|query| query.matches());
+                if dark {
+                    Theme::Dark
+                } else {
+                    Theme::Light
+                }
+            }
+        }
+    }
+    fn apply(self) {
+        if let Some(root) = document_element() {
+            let _ = root.set_attribute("data-theme", self.as_str());
+        }
+    }
+}
+
+/// Names completing the identifier before the caret, listed below the input.
+struct Completion {
+    /// Byte offset of the identifier in the input.
+    start: usize,
+    candidates: Vec<Candidate>,
+    selected: usize,
+}
+
+/// A name that may be completed.
+struct Candidate {
+    name: String,
+    /// For functions, the definition and the doc on a line of its own, e.g.
+    /// `gcd(a, b)` and what it computes.
+    detail: Option<String>,
+}
+
+impl Candidate {
+    fn function(ident: &str, function: &Function) -> Self {
+        let params = function
+            .params()
+            .map_or("...".to_string(), |params| params.join(", "));
+        let body = function
+            .body()
+            .map_or(String::new(), |body| format!(" = {}", body));
+        let mut detail = format!("{}({}){}", ident, params, body);
+        if let Some(doc) = function.doc() {
+            detail.push('\n');
+            detail.push_str(doc);
+        }
+        Self {
+            name: format!("{}(", ident),
+            detail: Some(detail),
+        }
+    }
 }
 
 struct App {
     input: String,
+    tabs: Vec<Tab>,
+    /// Index in `tabs` of the open session, whose state is held below.
+    tab: usize,
     outputs: Vec<String>,
+    /// Indices of the long results shown in full.
+    expanded: HashSet<usize>,
+    /// Number of the last outputs rendered.
+    shown: usize,
+    /// Whether to scroll to the bottom after rendering.
+    scroll_to_bottom: bool,
+    history: History,
+    /// Submitted inputs waiting for the one being evaluated.
+    queue: VecDeque<String>,
+    pending: Option<Pending>,
+    /// Id of the last request to the worker.
+    request_id: u64,
+    worker: WorkerBridge<EvalWorker>,
+    /// The last evaluations, newest last.
+    undo: Vec<UndoStep>,
+    /// Index in `history` of the input shown while browsing it with the arrow
+    /// keys, and the unsubmitted input to return to.
+    browsing: Option<(usize, String)>,
+    completion: Option<Completion>,
+    /// Syntax errors of the submitted input, shown in and below it until it
+    /// is changed.
+    input_errors: Vec<Diagnostic>,
+    theme: Theme,
     env: Environment,
+    formatter: Formatter,
+    preferences: Preferences,
+    show_settings: bool,
+    show_variables: bool,
+    search: Option<Search>,
+    /// Whether to focus the search field after rendering.
+    focus_search: bool,
+    /// Whether to scroll to the current match after rendering.
+    scroll_to_match: bool,
     caret_pos: (usize, usize),
-    input_element: Option<HtmlInputElement>,
+    input_element: Option<HtmlTextAreaElement>,
     is_focused: bool,
 }
 
 impl App {
-    fn input_element(&mut self) -> Option<&HtmlInputElement> {
+    fn input_element(&mut self) -> Option<&HtmlTextAreaElement> {
         if self.input_element.is_none() {
             let input = web_sys::window()?
                 .document()?
                 .get_element_by_id("hidden-input")?
-                .dyn_into::<HtmlInputElement>()
+                .dyn_into::<HtmlTextAreaElement>()
                 .ok()?;
             self.input_element = Some(input);
         }
         self.input_element.as_ref()
     }
-    fn update_caret_pos(&mut self, input: &HtmlInputElement) {
+    fn update_caret_pos(&mut self, input: &HtmlTextAreaElement) {
         let start = input.selection_start();
         let end = input.selection_end();
         self.caret_pos.0 = start.unwrap().unwrap_or_default() as _;
@@ -47,20 +347,389 @@ impl App {
             .map(|end| self.input.len().min(end as _))
             .unwrap_or(self.input.len());
     }
-    fn submit_input(&mut self, input: &HtmlInputElement) {
-        self.outputs.push(format!("> {}", self.input));
-        self.outputs
-            .push(match parse_from_str::<Expr>(&self.input) {
-                Ok(expr) => match expr.eval(&mut self.env) {
-                    Ok(e) => format!("{}", e),
-                    Err(err) => format!("error: {}", err),
-                },
+    /// Variables, and functions followed by `(`, that may be completed.
+    fn names(&self) -> Vec<Candidate> {
+        let variables = self.env.variables().map(|(ident, _)| Candidate {
+            name: ident.to_string(),
+            detail: None,
+        });
+        let builtins = BUILTINS
+            .iter()
+            .map(|builtin| Candidate::function(builtin.name, &Function::Native(builtin)));
+        // User functions come before the builtins they shadow, which the
+        // stable sort keeps and `dedup_by` drops.
+        let functions = (self.env.functions())
+            .map(|(ident, function)| Candidate::function(ident, function))
+            .chain(builtins);
+        let mut names: Vec<_> = variables.chain(functions).collect();
+        names.sort_by(|a, b| a.name.cmp(&b.name));
+        names.dedup_by(|a, b| a.name == b.name);
+        names
+    }
+    /// Lists the names starting with the identifier before the caret, if any.
+    fn update_completion(&mut self) {
+        self.completion = None;
+        let Some(before) = self.input.get(..self.caret_pos.1) else {
+            return;
+        };
+        let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+        let start = before
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| is_ident(c))
+            .last()
+            .map_or(before.len(), |(i, _)| i);
+        let prefix = &before[start..];
+        if prefix.is_empty() || prefix.starts_with(|c: char| c.is_ascii_digit()) {
+            return;
+        }
+        let candidates: Vec<_> = (self.names().into_iter())
+            .filter(|candidate| candidate.name.starts_with(prefix) && candidate.name != prefix)
+            .collect();
+        if !candidates.is_empty() {
+            self.completion = Some(Completion {
+                start,
+                candidates,
+                selected: 0,
+            });
+        }
+    }
+    /// Replaces the identifier before the caret with the selected completion.
+    fn complete(&mut self, input: &HtmlTextAreaElement) {
+        let Some(completion) = self.completion.take() else {
+            return;
+        };
+        let name = &completion.candidates[completion.selected].name;
+        self.input
+            .replace_range(completion.start..self.caret_pos.1, name);
+        let caret = completion.start + name.len();
+        input.set_value(&self.input);
+        let pos = self.input[..caret].encode_utf16().count() as u32;
+        let _ = input.set_selection_range(pos, pos);
+        self.caret_pos = (caret, caret);
+    }
+    /// Replaces `range` of the input, leaving the caret after the replacement.
+    fn edit_input(&mut self, input: &HtmlTextAreaElement, range: Range<usize>, replacement: &str) {
+        let caret = range.start + replacement.len();
+        self.input.replace_range(range, replacement);
+        input.set_value(&self.input);
+        self.input_errors.clear();
+        self.move_caret(input, caret);
+    }
+    fn move_caret(&mut self, input: &HtmlTextAreaElement, caret: usize) {
+        let pos = self.input[..caret].encode_utf16().count() as u32;
+        let _ = input.set_selection_range(pos, pos);
+        self.caret_pos = (caret, caret);
+        self.update_completion();
+    }
+    /// Start and end of the line of the input the caret is on.
+    fn caret_line(&self) -> Range<usize> {
+        let caret = self.caret_pos.1;
+        let start = self.input[..caret].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.input[caret..]
+            .find('\n')
+            .map_or(self.input.len(), |i| caret + i);
+        start..end
+    }
+    /// Start of the word before the caret, skipping what separates them as
+    /// readline does.
+    fn word_start(&self) -> usize {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let before = self.input[..self.caret_pos.1].trim_end_matches(|c| !is_word(c));
+        before.trim_end_matches(is_word).len()
+    }
+    /// Replaces the input with the previous (`back`) or next submitted input,
+    /// placing the caret at its end. Going past the last one returns to the
+    /// input that was being written.
+    fn browse_history(&mut self, input: &HtmlTextAreaElement, back: bool) {
+        let len = self.history.entries().len();
+        let pos = match (&self.browsing, back) {
+            (None, true) if len > 0 => len - 1,
+            (Some((pos, _)), true) => pos.saturating_sub(1),
+            (Some((pos, _)), false) => pos + 1,
+            _ => return,
+        };
+        let draft = match self.browsing.take() {
+            Some((_, draft)) => draft,
+            None => mem::take(&mut self.input),
+        };
+        match self.history.entries().get(pos) {
+            Some(entry) => {
+                self.input = entry.input.clone();
+                self.browsing = Some((pos, draft));
+            }
+            None => self.input = draft,
+        }
+        input.set_value(&self.input);
+        let end = self.input.encode_utf16().count() as u32;
+        let _ = input.set_selection_range(end, end);
+        self.caret_pos = (self.input.len(), self.input.len());
+    }
+    /// Saves the outputs, history and environment, so that reloading the page
+    /// restores them.
+    fn save_session(&self) {
+        save(
+            &self.tabs[self.tab].key(),
+            &(&self.outputs, &self.history, &self.env),
+        );
+    }
+    fn save_tabs(&self) {
+        save(TABS_KEY, &(&self.tabs, self.tab));
+    }
+    /// Opens the session of the tab at `index`, after saving the open one.
+    fn select_tab(&mut self, index: usize) {
+        self.save_session();
+        self.open_tab(index);
+    }
+    fn open_tab(&mut self, index: usize) {
+        self.tab = index;
+        (self.outputs, self.history, self.env) = load(&self.tabs[index].key()).unwrap_or_default();
+        self.abandon();
+        self.undo.clear();
+        self.expanded.clear();
+        self.browsing = None;
+        self.completion = None;
+        self.input_errors.clear();
+        self.shown = SHOWN_OUTPUTS;
+        self.scroll_to_bottom = true;
+        self.save_tabs();
+    }
+    fn new_tab(&mut self) {
+        let id = self.tabs.iter().map(|tab| tab.id + 1).max().unwrap_or(0);
+        self.tabs.push(Tab::new(id));
+        self.select_tab(self.tabs.len() - 1);
+    }
+    /// Closes the tab at `index` and deletes its session, keeping at least one.
+    fn close_tab(&mut self, index: usize) {
+        if self.tabs.len() == 1 {
+            return;
+        }
+        let tab = self.tabs.remove(index);
+        if let Some(storage) = local_storage() {
+            let _ = storage.remove_item(&tab.key());
+        }
+        match index.cmp(&self.tab) {
+            Ordering::Less => self.tab -= 1,
+            Ordering::Equal => self.open_tab(index.min(self.tabs.len() - 1)),
+            Ordering::Greater => {}
+        }
+        self.save_tabs();
+    }
+    fn submit_input(&mut self, input: &HtmlTextAreaElement) {
+        if self.submit() {
+            input.set_value("");
+        }
+    }
+    /// Indices of the outputs containing the searched text, ignoring ASCII case.
+    fn search_matches(&self) -> Vec<usize> {
+        let Some(search) = self
+            .search
+            .as_ref()
+            .filter(|search| !search.query.is_empty())
+        else {
+            return Vec::new();
+        };
+        let query = search.query.to_ascii_lowercase();
+        (self.outputs.iter().enumerate())
+            .filter(|(_, output)| output.to_ascii_lowercase().contains(&query))
+            .map(|(i, _)| i)
+            .collect()
+    }
+    /// Goes to the match after the current one, or before it unless `forward`,
+    /// wrapping around. Without a current match, goes to the last one.
+    fn step_search(&mut self, forward: bool) {
+        let matches = self.search_matches();
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        let current = match search
+            .current
+            .and_then(|current| matches.iter().position(|&i| i == current))
+        {
+            Some(pos) if forward => matches.get(pos + 1).or(matches.first()),
+            Some(pos) => pos
+                .checked_sub(1)
+                .map_or(matches.last(), |pos| matches.get(pos)),
+            None => matches.last(),
+        };
+        search.current = current.copied();
+        if let Some(current) = search.current {
+            // Renders the outputs down from the match.
+            self.shown = self.shown.max(self.outputs.len() - current);
+            self.scroll_to_match = true;
+        }
+    }
+    /// Queues the input to run, returning whether it was taken. Syntax errors
+    /// keep it to be corrected, with the errors marked.
+    fn submit(&mut self) -> bool {
+        self.browsing = None;
+        if !self.input.trim_start().starts_with(':') {
+            if let Err(diagnostics) = self.check_input() {
+                self.input_errors = diagnostics;
+                return false;
+            }
+        }
+        if !self.input.trim().is_empty() {
+            self.queue.push_back(mem::take(&mut self.input));
+        }
+        self.input.clear();
+        self.completion = None;
+        self.run_queue();
+        true
+    }
+    /// Runs the queued inputs up to the next one to evaluate, which is sent to
+    /// the worker.
+    fn run_queue(&mut self) {
+        while self.pending.is_none() {
+            let Some(input) = self.queue.pop_front() else {
+                return;
+            };
+            if let Some(command) = input.trim().strip_prefix(':') {
+                let command = command.trim().to_string();
+                self.run_command(&command);
+                self.history.push(input, None);
+                continue;
+            }
+            let outputs = self.outputs.len();
+            self.outputs.push(format!("> {}", input));
+            match parse_with_spans::<Stmt>(&input) {
+                Ok((stmt, _)) if self.preferences.hints => self
+                    .outputs
+                    .extend(hints(&stmt).iter().map(|hint| format!("hint: {}", hint))),
+                Ok(_) => {}
+                // Only replayed inputs get here, which are skipped.
                 Err(err) => {
-                    format!("error: {}", err)
+                    let diagnostic = Diagnostic::new(&err, err.span);
+                    self.outputs
+                        .push(format!("error: {}", diagnostic.render(&input)));
+                    continue;
                 }
+            }
+            // A copy of the variables and functions, kept here meanwhile.
+            let mut env = Environment::default();
+            env.restore(self.env.snapshot());
+            self.request_id += 1;
+            self.worker.send(Request {
+                id: self.request_id,
+                input: input.clone(),
+                env,
             });
-        self.input.clear();
-        input.set_value("");
+            self.pending = Some(Pending {
+                id: self.request_id,
+                input,
+                outputs,
+            });
+        }
+    }
+    /// Takes the result of the pending input from the worker, and goes on with
+    /// the queue.
+    fn evaluated(&mut self, response: Response) {
+        let Some(pending) = self.pending.take_if(|pending| pending.id == response.id) else {
+            // Answers an input abandoned since.
+            return;
+        };
+        if self.undo.len() == UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        self.undo.push(UndoStep {
+            snapshot: self.env.snapshot(),
+            outputs: pending.outputs,
+            history: self.history.entries().len(),
+        });
+        self.env = response.env;
+        let value = match response.result {
+            Ok(value) => {
+                self.outputs
+                    .extend(value.as_ref().map(|value| self.formatter.format(value)));
+                value
+            }
+            Err(error) => {
+                self.outputs.push(format!("error: {}", error));
+                None
+            }
+        };
+        self.history.push(pending.input, value);
+        self.run_queue();
+    }
+    /// Stops at the pending input, which fails, and drops the queued ones. The
+    /// worker is left busy with it. Returns whether there was one.
+    fn cancel(&mut self) -> bool {
+        let Some(pending) = self.pending.take() else {
+            return false;
+        };
+        self.queue.clear();
+        self.outputs
+            .push(format!("error: {}", EvalErrorKind::Cancelled));
+        self.history.push(pending.input, None);
+        true
+    }
+    /// Forgets the pending and queued inputs, whose results no longer apply.
+    fn abandon(&mut self) {
+        self.pending = None;
+        self.queue.clear();
+    }
+    /// Runs the inputs of a shared session in a fresh one.
+    fn replay(&mut self, inputs: Vec<String>) {
+        self.abandon();
+        self.undo.clear();
+        self.outputs.clear();
+        self.expanded.clear();
+        self.history = History::default();
+        self.env = Environment::default();
+        self.queue.extend(inputs);
+        self.run_queue();
+    }
+    /// Runs the commands of the CLI that apply here.
+    fn run_command(&mut self, command: &str) {
+        match command {
+            "clear" => {
+                self.outputs.clear();
+                self.expanded.clear();
+                self.undo.clear();
+                if let Some(pending) = &mut self.pending {
+                    pending.outputs = 0;
+                }
+            }
+            "reset" => {
+                self.outputs.clear();
+                self.expanded.clear();
+                self.undo.clear();
+                self.abandon();
+                self.env = Environment::default();
+            }
+            _ => {
+                self.outputs.push(format!("> :{}", command));
+                self.outputs
+                    .push(format!("error: unknown command `:{}`", command));
+            }
+        }
+    }
+    /// Reverts the variables and functions to before the last evaluation, and
+    /// removes its input and outputs. Returns whether there was one.
+    fn undo(&mut self) -> bool {
+        if self.pending.is_some() {
+            // The result would be of the environment undone.
+            return false;
+        }
+        let Some(step) = self.undo.pop() else {
+            return false;
+        };
+        self.env.restore(step.snapshot);
+        self.outputs.truncate(step.outputs);
+        self.expanded.retain(|&i| i < step.outputs);
+        self.history.truncate(step.history);
+        self.browsing = None;
+        true
+    }
+    /// All syntax errors of the input, if any.
+    fn check_input(&self) -> Result<(), Vec<Diagnostic>> {
+        match parse_with_spans::<Stmt>(&self.input) {
+            Ok(_) => Ok(()),
+            // The script parser would take the lines for separate inputs.
+            Err(err) => Err((parse_script(&self.input).err())
+                .filter(|_| !self.input.contains('\n'))
+                .unwrap_or_else(|| vec![Diagnostic::new(&err, err.span)])),
+        }
     }
 }
 
@@ -68,15 +737,54 @@ impl Component for App {
     type Message = Msg;
     type Properties = ();
 
-    fn create(_ctx: &Context<Self>) -> Self {
-        Self {
+    fn create(ctx: &Context<Self>) -> Self {
+        let (tabs, tab) = load(TABS_KEY)
+            .filter(|(tabs, tab): &(Vec<Tab>, usize)| *tab < tabs.len())
+            .unwrap_or_else(|| (vec![Tab::new(0)], 0));
+        let (outputs, history, env) = load(&tabs[tab].key()).unwrap_or_default();
+        let theme = Theme::load();
+        theme.apply();
+        let worker = spawn_worker(ctx);
+        let mut app = Self {
             input: String::new(),
-            outputs: Vec::new(),
-            env: Environment::default(),
+            tabs,
+            tab,
+            outputs,
+            expanded: HashSet::new(),
+            shown: SHOWN_OUTPUTS,
+            scroll_to_bottom: true,
+            history,
+            queue: VecDeque::new(),
+            pending: None,
+            request_id: 0,
+            worker,
+            undo: Vec::new(),
+            browsing: None,
+            completion: None,
+            input_errors: Vec::new(),
+            theme,
+            env,
+            formatter: load(FORMATTER_KEY).unwrap_or_default(),
+            preferences: load(PREFERENCES_KEY).unwrap_or_default(),
+            show_settings: false,
+            show_variables: false,
+            search: None,
+            focus_search: false,
+            scroll_to_match: false,
             caret_pos: (0, 0),
             input_element: None,
             is_focused: false,
+        };
+        if let Some(inputs) = shared_inputs() {
+            app.new_tab();
+            app.replay(inputs);
+            app.save_session();
+            // Reloading goes on with the session rather than replaying it.
+            if let Some(window) = web_sys::window() {
+                let _ = window.location().set_hash("");
+            }
         }
+        app
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
@@ -95,15 +803,90 @@ impl Component for App {
             }
             Msg::InputChanged(event) => {
                 let target = event.target().unwrap();
-                let input = target.dyn_ref::<HtmlInputElement>().unwrap();
+                let input = target.dyn_ref::<HtmlTextAreaElement>().unwrap();
                 self.input = input.value();
+                self.input_errors.clear();
                 self.update_caret_pos(input);
+                self.update_completion();
                 true
             }
             Msg::KeyboardEvent(event) => match event.key().as_str() {
-                "Enter" if !event.is_composing() => {
+                // Shift+Enter inserts a new line, as does Enter in brackets.
+                "Enter"
+                    if !event.is_composing()
+                        && !event.shift_key()
+                        && !is_incomplete(&self.input) =>
+                {
+                    event.prevent_default();
+                    // Follows the new outputs unless reading earlier ones.
+                    self.scroll_to_bottom = is_scrolled_to_bottom();
+                    let target = event.target().unwrap();
+                    self.submit_input(target.dyn_ref::<HtmlTextAreaElement>().unwrap());
+                    self.save_session();
+                    true
+                }
+                // The shortcuts of readline, as in the CLI.
+                "l" if event.ctrl_key() => {
+                    event.prevent_default();
+                    self.run_command("clear");
+                    self.save_session();
+                    true
+                }
+                key @ ("u" | "a" | "e") if event.ctrl_key() && !event.is_composing() => {
+                    event.prevent_default();
+                    let target = event.target().unwrap();
+                    let input = target.dyn_ref::<HtmlTextAreaElement>().unwrap();
+                    let line = self.caret_line();
+                    match key {
+                        "u" => self.edit_input(input, line.start..self.caret_pos.1, ""),
+                        "a" => self.move_caret(input, line.start),
+                        _ => self.move_caret(input, line.end),
+                    }
+                    true
+                }
+                "Backspace" if event.alt_key() && !event.is_composing() => {
+                    event.prevent_default();
+                    let target = event.target().unwrap();
+                    let input = target.dyn_ref::<HtmlTextAreaElement>().unwrap();
+                    self.edit_input(input, self.word_start()..self.caret_pos.1, "");
+                    true
+                }
+                "z" if event.ctrl_key() && self.input.is_empty() => {
+                    event.prevent_default();
+                    let undone = self.undo();
+                    if undone {
+                        self.save_session();
+                    }
+                    undone
+                }
+                "Tab" if self.completion.is_some() => {
+                    event.prevent_default();
+                    let target = event.target().unwrap();
+                    self.complete(target.dyn_ref::<HtmlTextAreaElement>().unwrap());
+                    true
+                }
+                key @ ("ArrowUp" | "ArrowDown") if self.completion.is_some() => {
+                    event.prevent_default();
+                    let completion = self.completion.as_mut().unwrap();
+                    let len = completion.candidates.len();
+                    completion.selected = match key {
+                        "ArrowUp" => (completion.selected + len - 1) % len,
+                        _ => (completion.selected + 1) % len,
+                    };
+                    true
+                }
+                "Escape" if self.completion.is_some() => {
+                    self.completion = None;
+                    true
+                }
+                key @ ("ArrowUp" | "ArrowDown")
+                    if !event.is_composing() && !self.input.contains('\n') =>
+                {
+                    // Keeps the browser from moving the caret to either end.
+                    event.prevent_default();
                     let target = event.target().unwrap();
-                    self.submit_input(target.dyn_ref::<HtmlInputElement>().unwrap());
+                    let input = target.dyn_ref::<HtmlTextAreaElement>().unwrap();
+                    self.browse_history(input, key == "ArrowUp");
                     true
                 }
                 _ => {
@@ -117,9 +900,229 @@ impl Component for App {
             },
             Msg::SelectionChangeEvent(event) => {
                 let target = event.target().unwrap();
-                self.update_caret_pos(target.dyn_ref::<HtmlInputElement>().unwrap());
+                self.update_caret_pos(target.dyn_ref::<HtmlTextAreaElement>().unwrap());
+                self.update_completion();
+                true
+            }
+            Msg::Clear => {
+                self.run_command("clear");
+                self.save_session();
+                true
+            }
+            Msg::Reset => {
+                self.run_command("reset");
+                self.save_session();
+                true
+            }
+            Msg::ToggleTheme => {
+                self.theme = match self.theme {
+                    Theme::Light => Theme::Dark,
+                    Theme::Dark => Theme::Light,
+                };
+                self.theme.apply();
+                if let Some(storage) = local_storage() {
+                    let _ = storage.set_item(THEME_KEY, self.theme.as_str());
+                }
+                false
+            }
+            Msg::Share => {
+                if let Some(window) = web_sys::window() {
+                    if let (Ok(href), Some(fragment)) =
+                        (window.location().href(), share_link(&self.history))
+                    {
+                        let page = href.split('#').next().unwrap_or_default();
+                        let link = format!("{}{}", page, fragment);
+                        let _ = window.navigator().clipboard().write_text(&link);
+                    }
+                }
+                false
+            }
+            Msg::Export => {
+                // Errors only mean the browser would not start the download.
+                let _ = download(EXPORT_FILE, &transcript(&self.outputs));
+                false
+            }
+            Msg::NewTab => {
+                self.new_tab();
+                true
+            }
+            Msg::SelectTab(index) => {
+                if index != self.tab {
+                    self.select_tab(index);
+                }
                 true
             }
+            Msg::CloseTab(index) => {
+                self.close_tab(index);
+                true
+            }
+            Msg::ShowEarlier => {
+                self.shown += SHOWN_OUTPUTS;
+                true
+            }
+            Msg::Evaluated(response) => {
+                // Follows the new outputs unless reading earlier ones.
+                self.scroll_to_bottom = is_scrolled_to_bottom();
+                self.evaluated(*response);
+                self.save_session();
+                true
+            }
+            Msg::OpenSearch => {
+                if self.search.is_none() {
+                    self.search = Some(Search {
+                        query: String::new(),
+                        current: None,
+                    });
+                }
+                self.focus_search = true;
+                true
+            }
+            Msg::CloseSearch => {
+                self.search = None;
+                let _ = self.input_element().unwrap().focus();
+                true
+            }
+            Msg::SearchInput(query) => {
+                if let Some(search) = &mut self.search {
+                    search.query = query;
+                    search.current = None;
+                }
+                self.step_search(false);
+                true
+            }
+            Msg::SearchStep(forward) => {
+                self.step_search(forward);
+                true
+            }
+            Msg::ToggleExpanded(i) => {
+                if !self.expanded.remove(&i) {
+                    self.expanded.insert(i);
+                }
+                true
+            }
+            Msg::EditInput(text) => {
+                if let Some(input) = self.input_element().cloned() {
+                    self.browsing = None;
+                    self.edit_input(&input, 0..self.input.len(), &text);
+                }
+                true
+            }
+            Msg::RunScript(script) => {
+                self.scroll_to_bottom = true;
+                self.queue.extend(script_inputs(&script));
+                self.run_queue();
+                self.save_session();
+                true
+            }
+            Msg::Cancel => {
+                let cancelled = self.cancel();
+                if cancelled {
+                    self.worker = spawn_worker(ctx);
+                    self.save_session();
+                }
+                cancelled
+            }
+            Msg::Undo => {
+                self.undo();
+                self.save_session();
+                true
+            }
+            Msg::ToggleVariables => {
+                self.show_variables = !self.show_variables;
+                true
+            }
+            Msg::InsertName(name) => {
+                if let Some(input) = self.input_element().cloned() {
+                    self.edit_input(&input, self.caret_pos.0..self.caret_pos.1, &name);
+                }
+                true
+            }
+            Msg::ToggleSettings => {
+                self.show_settings = !self.show_settings;
+                true
+            }
+            Msg::SetSetting(setting, value) => {
+                let value = value.trim();
+                let formatter = &mut self.formatter;
+                match setting {
+                    Setting::Grouping => {
+                        formatter.grouping =
+                            value
+                                .parse()
+                                .ok()
+                                .filter(|&size| size > 0)
+                                .map(|size| DigitGrouping {
+                                    size,
+                                    ..Default::default()
+                                })
+                    }
+                    Setting::Radix => {
+                        formatter.radix = value
+                            .parse()
+                            .ok()
+                            .filter(|radix| (2..=36).contains(radix) && *radix != 10)
+                    }
+                    Setting::Precision => {
+                        formatter.significant_digits = value.parse().ok().filter(|&n| n > 0)
+                    }
+                }
+                save(FORMATTER_KEY, &self.formatter);
+                false
+            }
+            Msg::ToggleTypeset => {
+                self.preferences.typeset = !self.preferences.typeset;
+                save(PREFERENCES_KEY, &self.preferences);
+                true
+            }
+            Msg::ToggleHints => {
+                self.preferences.hints = !self.preferences.hints;
+                save(PREFERENCES_KEY, &self.preferences);
+                false
+            }
+            Msg::Zoom(larger) => {
+                let font_size = &mut self.preferences.font_size;
+                *font_size = match larger {
+                    Some(true) => *font_size + FONT_SIZE_STEP,
+                    Some(false) => font_size.saturating_sub(FONT_SIZE_STEP),
+                    None => Preferences::default().font_size,
+                }
+                .clamp(*FONT_SIZES.start(), *FONT_SIZES.end());
+                save(PREFERENCES_KEY, &self.preferences);
+                true
+            }
+            Msg::Copy(text) => {
+                if let Some(window) = web_sys::window() {
+                    // The promise only tells whether the browser allowed it.
+                    let _ = window.navigator().clipboard().write_text(&text);
+                }
+                false
+            }
+        }
+    }
+
+    fn rendered(&mut self, _ctx: &Context<Self>, _first_render: bool) {
+        if mem::take(&mut self.scroll_to_bottom) {
+            if let Some(root) = document_element() {
+                root.set_scroll_top(root.scroll_height());
+            }
+        }
+        let document = web_sys::window().and_then(|window| window.document());
+        if let Some(document) = document {
+            let current = self.search.as_ref().and_then(|search| search.current);
+            if let Some(current) = current.filter(|_| mem::take(&mut self.scroll_to_match)) {
+                if let Some(line) = document.get_element_by_id(&format!("output-{}", current)) {
+                    line.scroll_into_view_with_bool(false);
+                }
+            }
+            if mem::take(&mut self.focus_search) {
+                let field = document.get_element_by_id("search-input");
+                if let Some(field) =
+                    field.and_then(|field| field.dyn_into::<HtmlInputElement>().ok())
+                {
+                    let _ = field.focus();
+                    field.select();
+                }
+            }
         }
     }
 
@@ -127,7 +1130,26 @@ impl Component for App {
         let input_onkeydown = ctx
             .link()
             .callback(|e: KeyboardEvent| Msg::KeyboardEvent(e));
-        let input_onclick = ctx.link().callback(|_e: MouseEvent| Msg::ClickEvent);
+        // Replaces the search of the browser, which the focus handling confuses,
+        // and its zoom, to scale only the outputs and the input.
+        let main_onkeydown = ctx.link().batch_callback(|event: KeyboardEvent| {
+            if !event.ctrl_key() {
+                return None;
+            }
+            let msg = match event.key().as_str() {
+                "f" => Msg::OpenSearch,
+                "=" | "+" => Msg::Zoom(Some(true)),
+                "-" => Msg::Zoom(Some(false)),
+                "0" => Msg::Zoom(None),
+                _ => return None,
+            };
+            event.prevent_default();
+            Some(msg)
+        });
+        // Clicks from the keyboard, on a focused button, leave the focus there.
+        let input_onclick = ctx
+            .link()
+            .batch_callback(|e: MouseEvent| (e.detail() != 0).then_some(Msg::ClickEvent));
         let input_oninput = ctx
             .link()
             .callback(|event: InputEvent| Msg::InputChanged(event));
@@ -136,19 +1158,116 @@ impl Component for App {
             .callback(|event: Event| Msg::SelectionChangeEvent(event));
         let input_onfocus = ctx.link().callback(|_e: FocusEvent| Msg::Focus);
         let input_onblur = ctx.link().callback(|_e: FocusEvent| Msg::Blur);
-        let (left, right) = self.input.split_at(self.caret_pos.1.min(self.input.len()));
-        let caret_classes = classes!("caret", self.is_focused.then(|| "is-focused"));
+        let caret_classes = classes!("caret", self.is_focused.then_some("is-focused"));
+        let caret = html!(<span class={ caret_classes }></span>);
+        let error_spans: Vec<_> = self.input_errors.iter().filter_map(|d| d.span).collect();
+        // Marks the errors at a position, such as the end of the input.
+        let markers = (error_spans.iter())
+            .filter(|span| span.start == span.end)
+            .map(|span| (span.start, html!(<span class="missing"></span>)))
+            .chain([(self.caret_pos.1, caret)])
+            .collect();
+
+        let onclear = ctx.link().callback(|_e: MouseEvent| Msg::Clear);
+        let onreset = ctx.link().callback(|_e: MouseEvent| Msg::Reset);
+        let ontheme = ctx.link().callback(|_e: MouseEvent| Msg::ToggleTheme);
+        let onsettings = ctx.link().callback(|_e: MouseEvent| Msg::ToggleSettings);
+        let onshare = ctx.link().callback(|_e: MouseEvent| Msg::Share);
+        let onundo = ctx.link().callback(|_e: MouseEvent| Msg::Undo);
+        let oncancel = ctx.link().callback(|_e: MouseEvent| Msg::Cancel);
+        // Lets files be dropped rather than opened by the browser.
+        let ondragover = Callback::from(|event: DragEvent| event.prevent_default());
+        let link = ctx.link().clone();
+        let ondrop = Callback::from(move |event: DragEvent| {
+            event.prevent_default();
+            if let Some(file) = dropped_script(&event) {
+                let link = link.clone();
+                spawn_local(async move {
+                    if let Some(script) = JsFuture::from(file.text())
+                        .await
+                        .ok()
+                        .and_then(|text| text.as_string())
+                    {
+                        link.send_message(Msg::RunScript(script));
+                    }
+                });
+            }
+        });
+        let onvariables = ctx.link().callback(|_e: MouseEvent| Msg::ToggleVariables);
+        let onexport = ctx.link().callback(|_e: MouseEvent| Msg::Export);
+        let onearlier = ctx.link().callback(|_e: MouseEvent| Msg::ShowEarlier);
+        let hidden = self.outputs.len().saturating_sub(self.shown);
 
         html! {
-            <main onclick={ input_onclick }>
-                { for self.outputs.iter().map(|output| html!(<pre class="line">{ output }</pre>)) }
+            <main
+                style={ format!("--font-scale: {}", self.preferences.font_size as f64 / 100.0) }
+                onclick={ input_onclick }
+                onkeydown={ main_onkeydown }
+                { ondragover }
+                { ondrop }
+            >
+                { view_tabs(ctx, &self.tabs, self.tab) }
+                <nav class="toolbar">
+                    <button title="Undo the last evaluation (Ctrl+Z)" disabled={ self.undo.is_empty() || self.pending.is_some() } onclick={ onundo }>{ "Undo" }</button>
+                    <button title="Clear the outputs (:clear)" onclick={ onclear }>{ "Clear" }</button>
+                    <button title="Clear the outputs and variables (:reset)" onclick={ onreset }>{ "Reset" }</button>
+                    <button title="Switch between light and dark colors" onclick={ ontheme }>{ "Theme" }</button>
+                    <button title="How results are written" aria-pressed={ self.show_settings.to_string() } onclick={ onsettings }>{ "Settings" }</button>
+                    <button title="List the variables and functions" aria-pressed={ self.show_variables.to_string() } onclick={ onvariables }>{ "Variables" }</button>
+                    <button title="Copy a link that replays the inputs" onclick={ onshare }>{ "Share" }</button>
+                    <button title="Download the outputs as Markdown" onclick={ onexport }>{ "Export" }</button>
+                </nav>
+                if self.show_settings {
+                    { view_settings(ctx, &self.formatter, &self.preferences) }
+                }
+                if self.show_variables {
+                    { view_variables(ctx, &self.env, &self.formatter) }
+                }
+                if hidden > 0 {
+                    <button class="earlier" onclick={ onearlier }>
+                        { format!("Show earlier outputs ({} hidden)", hidden) }
+                    </button>
+                }
+                if let Some(search) = &self.search {
+                    { view_search(ctx, search, &self.search_matches()) }
+                }
+                <div class="transcript" role="log" aria-label="Outputs">
+                    { for (self.outputs.iter().enumerate().skip(hidden)).map(|(i, output)| {
+                        let expanded = self.expanded.contains(&i);
+                        let typeset = self.preferences.typeset;
+                        view_output(ctx, i, output, expanded, typeset, self.search.as_ref())
+                    }) }
+                </div>
+                if let Some(pending) = &self.pending {
+                    // Keyed so that the delay of showing it starts anew.
+                    <div class="progress" key={ pending.id }>
+                        <span class="spinner" role="status" aria-label="Evaluating"></span>
+                        <button title="Stop the evaluation" onclick={ oncancel }>{ "Cancel" }</button>
+                    </div>
+                }
                 <div class="input-area">
-                    <pre class="input-cover">
-                        { "> " }{ left }<span class={ caret_classes }></span>{ right }
+                    // Draws the textarea below, which is what assistive technology
+                    // reads.
+                    <pre class="input-cover" aria-hidden="true">
+                        <span class="prompt">{ "> " }</span>
+                        { view_input(&self.input, markers, &error_spans) }
                     </pre>
-                    <input
-                        type="text"
+                    <div id="input-errors" role="alert">
+                        { for self.input_errors.iter().map(|diagnostic| html! {
+                            <pre class="line error">{ format!("error: {}", diagnostic) }</pre>
+                        }) }
+                    </div>
+                    { for self.completion.iter().map(view_completion) }
+                    <textarea
+                        rows="1"
                         id="hidden-input"
+                        aria-label="Expression, run with Enter"
+                        aria-describedby="input-errors"
+                        aria-invalid={ (!self.input_errors.is_empty()).then_some("true") }
+                        aria-autocomplete="list"
+                        aria-controls="completion"
+                        aria-expanded={ self.completion.is_some().to_string() }
+                        aria-activedescendant={ self.completion.as_ref().map(|completion| format!("completion-{}", completion.selected)) }
                         oninput={ input_oninput }
                         onkeydown={ input_onkeydown }
                         onselectionchange={ input_onselectionchange }
@@ -161,6 +1280,541 @@ impl Component for App {
     }
 }
 
+/// Buttons opening and closing the sessions, and one adding another.
+fn view_tabs(ctx: &Context<App>, tabs: &[Tab], selected: usize) -> Html {
+    let closable = tabs.len() > 1;
+    let items = tabs.iter().enumerate().map(|(i, tab)| {
+        let onselect = ctx.link().callback(move |_e: MouseEvent| Msg::SelectTab(i));
+        let onclose = ctx.link().callback(move |_e: MouseEvent| Msg::CloseTab(i));
+        let current = (i == selected).then_some("true");
+        html! {
+            <span class={ classes!("tab", current.map(|_| "selected")) }>
+                <button aria-current={ current } onclick={ onselect }>{ &tab.name }</button>
+                if closable {
+                    <button
+                        class="close"
+                        title="Close and delete the session"
+                        aria-label={ format!("Close {}", tab.name) }
+                        onclick={ onclose }
+                    >
+                        { "×" }
+                    </button>
+                }
+            </span>
+        }
+    });
+    let onnew = ctx.link().callback(|_e: MouseEvent| Msg::NewTab);
+    html! {
+        <nav class="tabs">
+            { for items }
+            <button title="Start a new session" onclick={ onnew }>{ "+" }</button>
+        </nav>
+    }
+}
+
+/// The variables and user functions with their values, whose names are
+/// inserted at the caret when clicked.
+fn view_variables(ctx: &Context<App>, env: &Environment, formatter: &Formatter) -> Html {
+    let mut variables: Vec<_> = (env.variables())
+        .map(|(ident, value)| (ident.to_string(), format!(" = {}", formatter.format(value))))
+        .collect();
+    variables.sort();
+    let mut functions: Vec<_> = (env.functions())
+        .map(|(ident, function)| {
+            let params = function
+                .params()
+                .map_or("...".to_string(), |params| params.join(", "));
+            let body = function
+                .body()
+                .map_or(String::new(), |body| format!(" = {}", body));
+            (format!("{}(", ident), format!("{}){}", params, body))
+        })
+        .collect();
+    functions.sort();
+    let items = variables.into_iter().chain(functions).map(|(name, rest)| {
+        let onclick = ctx.link().callback({
+            let name = name.clone();
+            move |_e: MouseEvent| Msg::InsertName(name.clone())
+        });
+        html! {
+            <li>
+                <button class="name" title="Insert at the caret" { onclick }>{ name }</button>
+                { rest }
+            </li>
+        }
+    });
+    html! {
+        <aside class="variables">
+            <ul>{ for items }</ul>
+        </aside>
+    }
+}
+
+/// Fields for the settings of `formatter`, applied to later results.
+fn view_settings(ctx: &Context<App>, formatter: &Formatter, preferences: &Preferences) -> Html {
+    let field = |setting: Setting,
+                 label: &str,
+                 value: Option<String>,
+                 min: &'static str,
+                 max: &'static str| {
+        let oninput = ctx.link().callback(move |event: InputEvent| {
+            let input: HtmlInputElement = event.target_unchecked_into();
+            Msg::SetSetting(setting, input.value())
+        });
+        html! {
+            <label>
+                { label }
+                <input type="number" { min } { max } value={ value.unwrap_or_default() } { oninput } />
+            </label>
+        }
+    };
+    let ontypeset = ctx.link().callback(|_e: Event| Msg::ToggleTypeset);
+    let onhints = ctx.link().callback(|_e: Event| Msg::ToggleHints);
+    let onsmaller = ctx.link().callback(|_e: MouseEvent| Msg::Zoom(Some(false)));
+    let onlarger = ctx.link().callback(|_e: MouseEvent| Msg::Zoom(Some(true)));
+    // Keeps the click from focusing the input of expressions.
+    let onclick = Callback::from(|event: MouseEvent| event.stop_propagation());
+    html! {
+        <div class="settings" { onclick }>
+            { field(
+                Setting::Grouping,
+                "Digit group size",
+                formatter.grouping.map(|grouping| grouping.size.to_string()),
+                "0",
+                "",
+            ) }
+            { field(
+                Setting::Radix,
+                "Integer base",
+                Some(formatter.radix.unwrap_or(10).to_string()),
+                "2",
+                "36",
+            ) }
+            { field(
+                Setting::Precision,
+                "Significant digits",
+                formatter.significant_digits.map(|digits| digits.to_string()),
+                "1",
+                "",
+            ) }
+            <div class="row">
+                { "Font size" }
+                <span>
+                    <button title="Smaller (Ctrl+-)" onclick={ onsmaller }>{ "−" }</button>
+                    { format!(" {}% ", preferences.font_size) }
+                    <button title="Larger (Ctrl+=)" onclick={ onlarger }>{ "+" }</button>
+                </span>
+            </div>
+            <label>
+                { "Typeset inputs" }
+                <input type="checkbox" checked={ preferences.typeset } onchange={ ontypeset } />
+            </label>
+            <label>
+                { "Precedence hints" }
+                <input type="checkbox" checked={ preferences.hints } onchange={ onhints } />
+            </label>
+        </div>
+    }
+}
+
+/// The search field, with the number of the match gone to and buttons going
+/// to the others.
+fn view_search(ctx: &Context<App>, search: &Search, matches: &[usize]) -> Html {
+    let oninput = ctx.link().callback(|event: InputEvent| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        Msg::SearchInput(input.value())
+    });
+    let onkeydown = ctx
+        .link()
+        .batch_callback(|event: KeyboardEvent| match event.key().as_str() {
+            "Enter" => Some(Msg::SearchStep(!event.shift_key())),
+            "Escape" => Some(Msg::CloseSearch),
+            _ => None,
+        });
+    let onprevious = ctx.link().callback(|_e: MouseEvent| Msg::SearchStep(false));
+    let onnext = ctx.link().callback(|_e: MouseEvent| Msg::SearchStep(true));
+    let onclose = ctx.link().callback(|_e: MouseEvent| Msg::CloseSearch);
+    // Keeps the click from focusing the input of expressions.
+    let onclick = Callback::from(|event: MouseEvent| event.stop_propagation());
+    let position = search
+        .current
+        .and_then(|current| matches.iter().position(|&i| i == current));
+    let count = match position {
+        Some(pos) => format!("{} of {}", pos + 1, matches.len()),
+        None if search.query.is_empty() => String::new(),
+        None => "no matches".to_string(),
+    };
+    html! {
+        <div class="search" role="search" { onclick }>
+            <input
+                id="search-input"
+                type="search"
+                aria-label="Search the outputs"
+                value={ search.query.clone() }
+                { oninput }
+                { onkeydown }
+            />
+            <span role="status">{ count }</span>
+            <button title="Previous match (Shift+Enter)" onclick={ onprevious }>{ "↑" }</button>
+            <button title="Next match (Enter)" onclick={ onnext }>{ "↓" }</button>
+            <button title="Close (Escape)" onclick={ onclose }>{ "×" }</button>
+        </div>
+    }
+}
+
+/// `text` with the occurrences of `query` marked, ignoring ASCII case.
+fn mark_matches(text: &str, query: &str) -> Html {
+    if query.is_empty() {
+        return html!({ text });
+    }
+    let lower = text.to_ascii_lowercase();
+    let query = query.to_ascii_lowercase();
+    let mut parts = Vec::new();
+    let mut last = 0;
+    for (start, _) in lower.match_indices(&query) {
+        let end = start + query.len();
+        parts.push(html!({ &text[last..start] }));
+        parts.push(html!(<mark>{ &text[start..end] }</mark>));
+        last = end;
+    }
+    parts.push(html!({ &text[last..] }));
+    parts.into_iter().collect()
+}
+
+/// `output` with only its ends, if it is long enough to be collapsed, e.g.
+/// `1234…(9,988 more digits)…4321`.
+fn collapse(output: &str) -> Option<String> {
+    let len = output.chars().count();
+    if len <= COLLAPSE_LENGTH {
+        return None;
+    }
+    let head_end = output.char_indices().nth(COLLAPSED_END)?.0;
+    let tail_start = output.char_indices().nth(len - COLLAPSED_END)?.0;
+    let omitted = &output[head_end..tail_start];
+    // Numbers in bases above 10 have letters for digits.
+    let is_number = output
+        .trim_start_matches('-')
+        .starts_with(|c: char| c.is_ascii_digit())
+        && (omitted.chars()).all(|c| c.is_ascii_alphanumeric() || ",._ '".contains(c));
+    let count = if is_number {
+        let digits = omitted.chars().filter(char::is_ascii_alphanumeric).count();
+        format!("{} more digits", group_thousands(digits))
+    } else {
+        format!(
+            "{} more characters",
+            group_thousands(omitted.chars().count())
+        )
+    };
+    Some(format!(
+        "{}…({})…{}",
+        &output[..head_end],
+        count,
+        &output[tail_start..]
+    ))
+}
+
+/// `n` with a comma between each group of three digits.
+fn group_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// An input as MathML, if it is an expression.
+fn input_mathml(input: &str) -> Option<Html> {
+    match parse_with_spans::<Stmt>(input).ok()?.0 {
+        Stmt::Expr(expr) => Some(Html::from_html_unchecked(expr.to_mathml().into())),
+        _ => None,
+    }
+}
+
+/// An output line, colored by its kind, with a button copying it without the
+/// prompt of inputs. Long results are collapsed unless `expanded`, inputs are
+/// typeset below if `typeset`, and matches of `search` are marked.
+fn view_output(
+    ctx: &Context<App>,
+    i: usize,
+    output: &str,
+    expanded: bool,
+    typeset: bool,
+    search: Option<&Search>,
+) -> Html {
+    let query = search.map_or("", |search| &search.query);
+    let is_result = !["> ", "error: ", "hint: "]
+        .iter()
+        .any(|prefix| output.starts_with(prefix));
+    let collapsed = collapse(output).filter(|_| is_result);
+    let shown = collapsed.as_deref().filter(|_| !expanded).unwrap_or(output);
+    let (class, text) = match output.strip_prefix("> ") {
+        Some(input) => (
+            "input",
+            html!(<><span class="prompt">{ "> " }</span>{ mark_matches(input, query) }</>),
+        ),
+        None if output.starts_with("error: ") => ("error", mark_matches(output, query)),
+        None if output.starts_with("hint: ") => ("hint", mark_matches(output, query)),
+        None => ("result", mark_matches(shown, query)),
+    };
+    let current = search.is_some_and(|search| search.current == Some(i));
+    let copied = output.strip_prefix("> ").unwrap_or(output).to_string();
+    let onedit = output.strip_prefix("> ").map(|input| {
+        let input = input.to_string();
+        ctx.link().callback(move |_| Msg::EditInput(input.clone()))
+    });
+    let title = onedit.is_some().then_some("Edit again");
+    let math = (output.strip_prefix("> "))
+        .filter(|_| typeset)
+        .and_then(input_mathml);
+    let onclick = ctx.link().callback(move |_| Msg::Copy(copied.clone()));
+    let ontoggle = ctx.link().callback(move |_| Msg::ToggleExpanded(i));
+    html! {
+        <div id={ format!("output-{}", i) } class={ classes!("line", class, current.then_some("current")) }>
+            <pre { title } onclick={ onedit }>{ text }</pre>
+            if let Some(math) = math {
+                <div class="math">{ math }</div>
+            }
+            if collapsed.is_some() {
+                <button class="expand" onclick={ ontoggle }>
+                    { if expanded { "collapse" } else { "expand" } }
+                </button>
+            }
+            <button class="copy" title="Copy the full value" { onclick }>{ "copy" }</button>
+        </div>
+    }
+}
+
+/// Class to highlight a token with.
+fn token_class(token: &Token) -> &'static str {
+    match token {
+        Token::NumLit(_) | Token::Superscript(_) => "number",
+        Token::VarLit(_) => "ident",
+        Token::Keyword(_) => "keyword",
+        Token::Doc(_) => "doc",
+        token if token.bracket().is_some() => "bracket",
+        _ => "operator",
+    }
+}
+
+/// Spans of the tokens of `input` in order, with the class to highlight them
+/// with. Brackets without a matching one and unexpected characters are marked.
+fn highlight(input: &str) -> Vec<(Span, &'static str)> {
+    let (tokens, errors) = Lexer::new(input).collect_recovering();
+    let mut spans: Vec<_> = (tokens.iter())
+        .map(|token| (token.span, token_class(&token.value)))
+        .collect();
+    let mut open = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.value.closer().is_some() {
+            open.push(i);
+        } else if let Some(bracket) = token.value.bracket() {
+            match open.last() {
+                Some(&j) if tokens[j].value.closer() == Some(bracket) => {
+                    open.pop();
+                }
+                _ => spans[i].1 = "unmatched",
+            }
+        }
+    }
+    for i in open {
+        spans[i].1 = "unmatched";
+    }
+    spans.extend(
+        errors
+            .iter()
+            .filter_map(|err| err.span.map(|span| (span, "error"))),
+    );
+    spans.sort_by_key(|(span, _)| span.start);
+    spans
+}
+
+/// Renders `input` highlighted, with tokens overlapping `errors` marked and
+/// each of `markers`, such as the caret, at its byte offset.
+fn view_input(input: &str, mut markers: Vec<(usize, Html)>, errors: &[Span]) -> Html {
+    // Highlighted and plain pieces covering the input.
+    let mut pieces = Vec::new();
+    let mut pos = 0;
+    for (span, class) in highlight(input) {
+        if span.start >= pos {
+            let is_error = (errors.iter()).any(|e| e.start < span.end && span.start < e.end);
+            pieces.push((pos..span.start, None));
+            pieces.push((span.range(), Some(if is_error { "error" } else { class })));
+            pos = span.end;
+        }
+    }
+    pieces.push((pos..input.len(), None));
+    markers.sort_by_key(|&(pos, _)| pos);
+    let mut markers = markers.into_iter().peekable();
+    let mut html = Vec::new();
+    for (range, class) in pieces {
+        // Splits the piece at the markers in it.
+        let mut start = range.start;
+        loop {
+            while let Some((_, marker)) = markers.next_if(|&(pos, _)| pos <= start) {
+                html.push(marker);
+            }
+            let end = markers
+                .peek()
+                .map_or(range.end, |&(pos, _)| pos.min(range.end));
+            let text = input.get(start..end).unwrap_or_default().to_string();
+            match class {
+                _ if text.is_empty() => {}
+                Some(class) => html.push(html!(<span { class }>{ text }</span>)),
+                None => html.push(html!({ text })),
+            }
+            if end >= range.end {
+                break;
+            }
+            start = end;
+        }
+    }
+    html.extend(markers.map(|(_, marker)| marker));
+    html.into_iter().collect()
+}
+
+fn view_completion(completion: &Completion) -> Html {
+    let items = completion.candidates.iter().enumerate().map(|(i, candidate)| {
+        let selected = i == completion.selected;
+        let class = classes!(selected.then_some("selected"));
+        // The selected function shows its definition and doc below its name.
+        let detail = (candidate.detail.as_ref())
+            .filter(|_| selected)
+            .map(|detail| html!(<div class="detail">{ detail }</div>));
+        html! {
+            <li id={ format!("completion-{}", i) } role="option" aria-selected={ selected.to_string() } title={ candidate.detail.clone() } { class }>
+                { &candidate.name }
+                { for detail }
+            </li>
+        }
+    });
+    html!(<ul id="completion" class="completion" role="listbox">{ for items }</ul>)
+}
+
+/// The outputs as Markdown, with a code block for each input and what it
+/// printed.
+fn transcript(outputs: &[String]) -> String {
+    let mut markdown = String::from("# Calculator session\n");
+    for (i, output) in outputs.iter().enumerate() {
+        if i == 0 || output.starts_with("> ") {
+            if i != 0 {
+                markdown.push_str("```\n");
+            }
+            markdown.push_str("\n```text\n");
+        }
+        markdown.push_str(output);
+        markdown.push('\n');
+    }
+    if !outputs.is_empty() {
+        markdown.push_str("```\n");
+    }
+    markdown
+}
+
+/// Has the browser save `text` as a file named `name`.
+fn download(name: &str, text: &str) -> Result<(), JsValue> {
+    let options = BlobPropertyBag::new();
+    options.set_type("text/markdown");
+    let parts = js_sys::Array::of1(&JsValue::from_str(text));
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+    let document = web_sys::window().and_then(|window| window.document());
+    let anchor: HtmlAnchorElement = document
+        .ok_or(JsValue::NULL)?
+        .create_element("a")?
+        .unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+    Url::revoke_object_url(&url)
+}
+
+/// The root element, which scrolls the page.
+fn document_element() -> Option<Element> {
+    web_sys::window()?.document()?.document_element()
+}
+
+fn is_scrolled_to_bottom() -> bool {
+    document_element().is_none_or(|root| {
+        root.scroll_top() + root.client_height() + SCROLL_SLACK >= root.scroll_height()
+    })
+}
+
+fn local_storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// The dropped file if it is a script, named `*.calc` or of text.
+fn dropped_script(event: &DragEvent) -> Option<File> {
+    let file = event.data_transfer()?.files()?.get(0)?;
+    (file.name().ends_with(".calc") || file.type_().starts_with("text/")).then_some(file)
+}
+
+/// Starts a worker whose results come back as [`Msg::Evaluated`].
+fn spawn_worker(ctx: &Context<App>) -> WorkerBridge<EvalWorker> {
+    let link = ctx.link().clone();
+    EvalWorker::spawner()
+        .encoding::<Json>()
+        .callback(move |response| link.send_message(Msg::Evaluated(Box::new(response))))
+        .spawn(WORKER_PATH)
+}
+
+/// The URL fragment that replays the inputs of `history`: their JSON,
+/// deflated and in base64.
+fn share_link(history: &History) -> Option<String> {
+    let inputs: Vec<_> = history.entries().iter().map(|entry| &entry.input).collect();
+    let json = serde_json::to_vec(&inputs).ok()?;
+    let data = compress_to_vec(&json, 9);
+    Some(format!("{}{}", SHARE_PREFIX, URL_SAFE_NO_PAD.encode(data)))
+}
+
+/// The inputs in the URL fragment, if it is a [`share_link`].
+fn shared_inputs() -> Option<Vec<String>> {
+    let hash = web_sys::window()?.location().hash().ok()?;
+    let data = URL_SAFE_NO_PAD
+        .decode(hash.strip_prefix(SHARE_PREFIX)?)
+        .ok()?;
+    let json = decompress_to_vec_with_limit(&data, SHARE_LIMIT).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+/// The value saved under `key`, if any.
+fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let json = local_storage()?.get_item(key).ok()??;
+    serde_json::from_str(&json).ok()
+}
+
+fn save<T: Serialize>(key: &str, value: &T) {
+    if let (Some(storage), Ok(json)) = (local_storage(), serde_json::to_string(value)) {
+        // Fails when the storage is full, which only loses the value.
+        let _ = storage.set_item(key, &json);
+    }
+}
+
+/// Registers the service worker, if the browser supports it. The page works
+/// without it, only not offline.
+fn register_service_worker() {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    if js_sys::Reflect::has(&window.navigator(), &"serviceWorker".into()).unwrap_or(false) {
+        let promise = window
+            .navigator()
+            .service_worker()
+            .register(SERVICE_WORKER_PATH);
+        spawn_local(async move {
+            // Fails on pages not served over HTTPS or from localhost.
+            let _ = JsFuture::from(promise).await;
+        });
+    }
+}
+
 fn main() {
+    #[cfg(feature = "panic-hook")]
+    console_error_panic_hook::set_once();
+    register_service_worker();
     yew::Renderer::<App>::new().render();
 }
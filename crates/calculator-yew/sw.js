@@ -0,0 +1,43 @@
+// Serves the app offline from the files of its last load.
+//
+// Everything is fetched from the network first, so a new build is used as
+// soon as it is deployed, and the cache only answers when that fails. Trunk
+// names the files of each build differently, so each load of the page from
+// the network empties the cache of the previous build before the page fetches
+// its own files again.
+
+const CACHE = "calculator";
+
+self.addEventListener("install", () => self.skipWaiting());
+
+self.addEventListener("activate", (event) => event.waitUntil(self.clients.claim()));
+
+self.addEventListener("fetch", (event) => {
+    const request = event.request;
+    if (request.method !== "GET" || new URL(request.url).origin !== self.location.origin) {
+        return;
+    }
+    event.respondWith(fetchAndCache(request));
+});
+
+async function fetchAndCache(request) {
+    // Pages are stored under the scope, whatever the query string.
+    const key = request.mode === "navigate" ? self.registration.scope : request;
+    const cache = await caches.open(CACHE);
+    try {
+        const response = await fetch(request);
+        if (response.ok) {
+            if (request.mode === "navigate") {
+                await Promise.all((await cache.keys()).map((old) => cache.delete(old)));
+            }
+            await cache.put(key, response.clone());
+        }
+        return response;
+    } catch (error) {
+        const cached = await cache.match(key);
+        if (cached) {
+            return cached;
+        }
+        throw error;
+    }
+}